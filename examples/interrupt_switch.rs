@@ -0,0 +1,70 @@
+//! examples/interrupt_switch.rs
+#![no_main]
+#![no_std]
+use log::info;
+// Includes a panic handler and optional logging facilities
+use libdaisy_rust::logger;
+
+use libdaisy_rust::exti;
+use libdaisy_rust::gpio::*;
+use libdaisy_rust::prelude::*;
+use libdaisy_rust::system;
+
+#[rtic::app(
+    device = stm32h7xx_hal::stm32,
+    peripherals = true,
+    monotonic = rtic::cyccnt::CYCCNT,
+)]
+const APP: () = {
+    struct Resources {
+        seed_led: SeedLed,
+        switch1: exti::InterruptInput<Daisy28<Input<PullUp>>>,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> init::LateResources {
+        logger::init();
+        let mut system = system::System::init(ctx.core, ctx.device);
+
+        let daisy28 = system
+            .gpio
+            .daisy28
+            .take()
+            .expect("Failed to get pin daisy28!")
+            .into_pull_up_input();
+
+        // Daisy28 is PA2, which is on EXTI line 2
+        let switch1 = exti::InterruptInput::new(
+            daisy28,
+            exti::Edge::RISING_FALLING,
+            &mut system.exti,
+            &mut system.syscfg,
+        );
+
+        init::LateResources {
+            seed_led: system.gpio.led,
+            switch1,
+        }
+    }
+
+    #[idle]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    #[task( binds = EXTI2, resources = [seed_led, switch1] )]
+    fn switch_handler(ctx: switch_handler::Context) {
+        let switch1 = ctx.resources.switch1;
+        switch1.clear();
+
+        // The switch pulls the pin low when pressed
+        if switch1.is_low() {
+            info!("Button pressed!");
+            ctx.resources.seed_led.set_high().unwrap();
+        } else {
+            ctx.resources.seed_led.set_low().unwrap();
+        }
+    }
+};
@@ -0,0 +1,78 @@
+//! examples/pod.rs
+#![no_main]
+#![no_std]
+use log::info;
+
+use libdaisy_rust::audio;
+use libdaisy_rust::board::pod::Pod;
+use libdaisy_rust::logger;
+use libdaisy_rust::system;
+
+#[rtic::app(
+    device = stm32h7xx_hal::stm32,
+    peripherals = true,
+    monotonic = rtic::cyccnt::CYCCNT,
+)]
+const APP: () = {
+    struct Resources {
+        audio: audio::Audio,
+        pod: Pod,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> init::LateResources {
+        logger::init();
+        let system = system::System::init(ctx.core, ctx.device);
+        let (pod, audio, _) = Pod::init(system);
+
+        info!("Startup done!");
+
+        init::LateResources { audio, pod }
+    }
+
+    #[idle]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    // Interrupt handler for audio, should not generally need to be modified
+    #[task( binds = SAI1, resources = [audio], priority = 8 )]
+    fn audio_handler(ctx: audio_handler::Context) {
+        let audio = ctx.resources.audio;
+        audio.read();
+
+        if let Some(stereo_iter) = audio.input.get_stereo_iter() {
+            for (left, right) in stereo_iter {
+                audio.output.push((left, right)).unwrap();
+            }
+        }
+
+        audio.send();
+    }
+
+    #[task( binds = TIM2, resources = [pod] )]
+    fn interface_handler(ctx: interface_handler::Context) {
+        let pod = ctx.resources.pod;
+        pod.timer2.clear_irq();
+
+        pod.process_analog_controls();
+        pod.process_digital_controls();
+
+        if pod.button1.is_rising() {
+            info!("Button 1 pressed!");
+        }
+        if pod.encoder.increment() != 0 {
+            info!("Encoder turned {}", pod.encoder.increment());
+        }
+
+        // Knobs set the color of LED 1, LED 2 lights while button 2 is held
+        let knob1 = pod.knob1.get_value();
+        let knob2 = pod.knob2.get_value();
+        pod.led1.set_color(knob1, knob2, 0.0);
+        let held = if pod.button2.is_pressed() { 1.0 } else { 0.0 };
+        pod.led2.set_color(0.0, 0.0, held);
+        pod.update_leds();
+    }
+};
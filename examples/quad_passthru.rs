@@ -0,0 +1,52 @@
+//! examples/quad_passthru.rs
+#![no_main]
+#![no_std]
+use log::info;
+
+use libdaisy_rust::audio;
+use libdaisy_rust::logger;
+use libdaisy_rust::system;
+
+#[rtic::app(
+    device = stm32h7xx_hal::stm32,
+    peripherals = true,
+    monotonic = rtic::cyccnt::CYCCNT,
+)]
+const APP: () = {
+    struct Resources {
+        audio: audio::Audio<{ audio::QUAD }>,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> init::LateResources {
+        logger::init();
+        let system = system::System::init_quad(ctx.core, ctx.device);
+
+        info!("Startup done!");
+
+        init::LateResources {
+            audio: system.audio,
+        }
+    }
+
+    // Non-default idle ensures chip doesn't go to sleep which causes issues for
+    // probe.rs currently
+    #[idle]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    // Interrupt handler for audio, services both SAI1 and SAI2
+    #[task( binds = SAI1, resources = [audio], priority = 8 )]
+    fn audio_handler(ctx: audio_handler::Context) {
+        let audio = ctx.resources.audio;
+
+        audio.process(
+            &mut |input: &[audio::Frame<4>], output: &mut [audio::Frame<4>]| {
+                output.copy_from_slice(input);
+            },
+        );
+    }
+};
@@ -1,149 +1,472 @@
 //! Audio module, handles audio startup and I/O
-//! As well as converting between the S24 input and f32 for processing
+//! As well as converting between the S24 input and f32 for processing, see `sample`
+//!
+//! Only the hardware independent parts are built without the `hal` feature.
+#[cfg(feature = "hal")]
+use cortex_m::peripheral::DWT;
+#[cfg(feature = "hal")]
+use log::info;
+#[cfg(feature = "hal")]
+use stm32h7xx_hal::time::Hertz;
+#[cfg(feature = "hal")]
 use stm32h7xx_hal::{sai, sai::*, stm32};
 
-use crate::system::{IoBuffer, BLOCK_SIZE_MAX};
+use crate::sample::{Dither, Sample};
+pub use crate::sample::{DitherMode, S24};
 
 // use core::marker::PhantomData;
-const FBIPMAX: f32 = 0.999985;
-const FBIPMIN: f32 = -FBIPMAX;
-const F32_TO_S24_SCALE: f32 = 8388608.0; // 2 ** 23
-const S24_TO_F32_SCALE: f32 = 1.0 / F32_TO_S24_SCALE;
-const S24_SIGN: i32 = 0x800000;
 
-type StereoIteratorHandle = fn(StereoIterator, &mut Output);
+/// Number of channels with only SAI1 in use
+pub const STEREO: usize = 2;
+/// Number of channels with SAI1 and SAI2 in use
+pub const QUAD: usize = 4;
+pub const MAX_CHANNELS: usize = QUAD;
+/// Frames `Audio::process` passes to the callback, without DMA the SAI FIFO delivers a
+/// single frame per interrupt
+pub const FRAMES_PER_CALLBACK: usize = 1;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct S24(pub i32);
+// Process samples at 1000 Hz
+// With a circular buffer(*2) for up to 4 channels
+pub const BLOCK_SIZE_MAX: usize = 48;
+pub const BUFFER_SIZE: usize = BLOCK_SIZE_MAX * MAX_CHANNELS * 2;
 
-impl From<i32> for S24 {
-    fn from(x: i32) -> S24 {
-        S24(x)
-    }
-}
+pub type IoBuffer = [u32; BUFFER_SIZE];
 
-impl From<u32> for S24 {
-    fn from(x: u32) -> S24 {
-        S24(x as i32)
-    }
+/// One sample for each channel
+pub type Frame<const CHANNELS: usize> = [f32; CHANNELS];
+
+/// Audio processing callback
+///
+/// Receives a block of input frames and fills an output block of the same length.
+/// Implemented for closures taking `(&[Frame<CHANNELS>], &mut [Frame<CHANNELS>])`.
+pub trait Callback<const CHANNELS: usize> {
+    fn process(&mut self, input: &[Frame<CHANNELS>], output: &mut [Frame<CHANNELS>]);
 }
 
-impl From<S24> for i32 {
-    fn from(x: S24) -> i32 {
-        x.0
+impl<F, const CHANNELS: usize> Callback<CHANNELS> for F
+where
+    F: FnMut(&[Frame<CHANNELS>], &mut [Frame<CHANNELS>]),
+{
+    fn process(&mut self, input: &[Frame<CHANNELS>], output: &mut [Frame<CHANNELS>]) {
+        self(input, output)
     }
 }
 
-impl From<S24> for u32 {
-    fn from(x: S24) -> u32 {
-        x.0 as u32
-    }
+/// Counts of audio FIFO errors since start, see [`Audio::stats`]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Stats {
+    /// No input frame was waiting in a SAI receive FIFO, the input was replaced by silence
+    pub input_xruns: u32,
+    /// A SAI transmit FIFO was full, or no output frame was pushed and silence was sent
+    pub output_xruns: u32,
 }
 
-impl From<f32> for S24 {
-    fn from(x: f32) -> S24 {
-        let x = if x <= FBIPMIN {
-            FBIPMIN
-        } else if x >= FBIPMAX {
-            FBIPMAX
-        } else {
-            x
-        };
-        S24((x * F32_TO_S24_SCALE) as i32)
-    }
+#[cfg(feature = "hal")]
+pub struct Audio<const CHANNELS: usize = STEREO> {
+    pub stream: sai::Sai<stm32::SAI1, sai::I2S>,
+    /// Channels 3 and 4, only present for quad audio
+    pub stream2: Option<sai::Sai<stm32::SAI2, sai::I2S>>,
+    pub input: Input<CHANNELS>,
+    pub output: Output<CHANNELS>,
+    stats: Stats,
 }
 
-impl From<S24> for f32 {
-    fn from(x: S24) -> f32 {
-        ((x.0 ^ S24_SIGN) - S24_SIGN) as f32 * S24_TO_F32_SCALE
+#[cfg(feature = "hal")]
+impl Audio {
+    pub fn new(
+        stream: sai::Sai<stm32::SAI1, sai::I2S>,
+        input: &'static mut IoBuffer,
+        output: &'static mut IoBuffer,
+    ) -> Self {
+        Self::start(stream, None, input, output)
     }
 }
 
-pub struct Audio {
-    pub stream: sai::Sai<stm32::SAI1, sai::I2S>,
-    pub input: Input,
-    pub output: Output,
+#[cfg(feature = "hal")]
+impl Audio<QUAD> {
+    /// SAI2 runs from its own clock pins, so its frames aren't synchronised to SAI1's and the
+    /// two pairs can be up to a frame apart. SAI2 is started before SAI1 and is serviced from
+    /// the SAI1 interrupt.
+    pub fn new_quad(
+        stream: sai::Sai<stm32::SAI1, sai::I2S>,
+        stream2: sai::Sai<stm32::SAI2, sai::I2S>,
+        input: &'static mut IoBuffer,
+        output: &'static mut IoBuffer,
+    ) -> Self {
+        Self::start(stream, Some(stream2), input, output)
+    }
 }
 
-impl Audio {
-    pub fn new(
+#[cfg(feature = "hal")]
+impl<const CHANNELS: usize> Audio<CHANNELS> {
+    fn start(
         mut stream: sai::Sai<stm32::SAI1, sai::I2S>,
+        mut stream2: Option<sai::Sai<stm32::SAI2, sai::I2S>>,
         input: &'static mut IoBuffer,
         output: &'static mut IoBuffer,
     ) -> Self {
+        if let Some(stream2) = &mut stream2 {
+            stream2.enable();
+            stream2.try_send(0, 0).unwrap();
+        }
         stream.listen(SaiChannel::ChannelB, Event::Data);
         stream.enable();
         stream.try_send(0, 0).unwrap();
         Audio {
             stream,
-            input: Input { buffer: input },
+            stream2,
+            input: Input::new(input),
             output: Output::new(output),
+            stats: Stats::default(),
         }
     }
 
+    pub fn channels(&self) -> usize {
+        CHANNELS
+    }
+
+    /// FIFO errors since start or the last `reset_stats`
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    /// Read the next input frame, missing input is replaced by silence
     pub fn read(&mut self) {
         self.stream
             .clear_irq(sai::SaiChannel::ChannelB, sai::Event::Data);
         self.output.reset();
-        if let Ok((left, right)) = self.stream.try_read() {
-            self.input.buffer[0] = left;
-            self.input.buffer[1] = right;
+        let (left, right) = self.stream.try_read().unwrap_or_else(|_| {
+            self.stats.input_xruns += 1;
+            (0, 0)
+        });
+        self.input.buffer[0] = left;
+        self.input.buffer[1] = right;
+        if let Some(stream2) = &mut self.stream2 {
+            let stats = &mut self.stats;
+            let (left, right) = stream2.try_read().unwrap_or_else(|_| {
+                stats.input_xruns += 1;
+                (0, 0)
+            });
+            self.input.buffer[2] = left;
+            self.input.buffer[3] = right;
         }
     }
 
+    /// Send the pushed output frame, or silence if none was pushed
+    ///
+    /// A full FIFO drops the frame rather than blocking the interrupt.
     pub fn send(&mut self) {
+        if self.output.index < CHANNELS {
+            self.stats.output_xruns += 1;
+            self.output.buffer[..CHANNELS].fill(0);
+        }
         let left = self.output.buffer[0];
         let right = self.output.buffer[1];
-        self.stream.try_send(left, right).unwrap();
+        if self.stream.try_send(left, right).is_err() {
+            self.stats.output_xruns += 1;
+        }
+        if let Some(stream2) = &mut self.stream2 {
+            let left = self.output.buffer[2];
+            let right = self.output.buffer[3];
+            if stream2.try_send(left, right).is_err() {
+                self.stats.output_xruns += 1;
+            }
+        }
+    }
+
+    /// Read input, run `callback` and send its output
+    pub fn process<C>(&mut self, callback: &mut C)
+    where
+        C: Callback<CHANNELS>,
+    {
+        self.read();
+
+        let mut input = [[0.0; CHANNELS]; FRAMES_PER_CALLBACK];
+        let mut output = [[0.0; CHANNELS]; FRAMES_PER_CALLBACK];
+        for (frame, dest) in self.input.frames().zip(input.iter_mut()) {
+            *dest = frame;
+        }
+        callback.process(&input, &mut output);
+        for frame in output.iter() {
+            self.output.push_frame(*frame).unwrap();
+        }
+
+        self.send();
     }
 
     // pub fn get_left(&self)
 }
 
-pub struct Input {
+/// Measures the share of the block period spent in the audio callback
+///
+/// Call `start` on entering the audio interrupt and `stop` before leaving it. Both only
+/// read the DWT cycle counter, so the meter can be left running.
+#[cfg(feature = "hal")]
+pub struct CpuLoadMeter {
+    /// Reciprocal of the core cycles between two callbacks
+    block_cycles_recip: f32,
+    /// Coefficient of the one pole smoothing of the average and the decay of the peak
+    smoothing: f32,
+    start: u32,
+    average: f32,
+    peak: f32,
+}
+
+#[cfg(feature = "hal")]
+impl CpuLoadMeter {
+    /// Callbacks process `block_size` frames at `sample_rate` on a core running at `core_clock`
+    ///
+    /// `block_size` is the number of frames per callback, `FRAMES_PER_CALLBACK` for
+    /// `Audio::process`, not the nominal `AUDIO_BLOCK_SIZE`, which would understate the load.
+    /// The average and peak settle over roughly one second.
+    pub fn new(sample_rate: Hertz, block_size: usize, core_clock: Hertz) -> Self {
+        let block_seconds = block_size as f32 / sample_rate.0 as f32;
+        Self {
+            block_cycles_recip: 1.0 / (block_seconds * core_clock.0 as f32),
+            smoothing: block_seconds,
+            start: 0,
+            average: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    /// Smoothing coefficient in the range (0.0, 1.0], higher responds faster
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn start(&mut self) {
+        self.start = DWT::get_cycle_count();
+    }
+
+    pub fn stop(&mut self) {
+        let cycles = DWT::get_cycle_count().wrapping_sub(self.start);
+        self.record(cycles);
+    }
+
+    /// Add a callback that took `cycles` core cycles
+    pub fn record(&mut self, cycles: u32) {
+        let load = cycles as f32 * self.block_cycles_recip;
+        self.average += (load - self.average) * self.smoothing;
+        self.peak = if load > self.peak {
+            load
+        } else {
+            self.peak - self.peak * self.smoothing
+        };
+    }
+
+    /// Smoothed load, 1.0 is the whole block period
+    pub fn average(&self) -> f32 {
+        self.average
+    }
+
+    /// Highest recent load, decays at the smoothing rate
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    pub fn reset(&mut self) {
+        self.average = 0.0;
+        self.peak = 0.0;
+    }
+
+    pub fn log(&self) {
+        info!(
+            "CPU load: average {:.1}%, peak {:.1}%",
+            self.average * 100.0,
+            self.peak * 100.0
+        );
+    }
+}
+
+pub struct Input<const CHANNELS: usize = STEREO> {
     buffer: &'static mut IoBuffer,
 }
 
-impl Input {
-    /// Get StereoIterator(interleaved) iterator
-    pub fn get_stereo_iter(&self) -> Option<StereoIterator> {
-        Some(StereoIterator::new(&self.buffer[..2]))
+impl<const CHANNELS: usize> Input<CHANNELS> {
+    /// `Audio` makes its own, this is for processing other buffers and for tests
+    pub fn new(buffer: &'static mut IoBuffer) -> Self {
+        Self { buffer }
+    }
+
+    /// Get StereoIterator(interleaved) iterator over the first two channels
+    pub fn get_stereo_iter(&self) -> Option<StereoIterator<'_>> {
+        Some(StereoIterator::new(&self.buffer[..CHANNELS], CHANNELS))
+    }
+
+    /// Get an iterator over whole frames
+    pub fn frames(&self) -> FrameIterator<'_, CHANNELS> {
+        FrameIterator::new(&self.buffer[..CHANNELS])
+    }
+
+    /// Get an iterator over the samples of `channel`, empty if there is no such channel
+    pub fn channel(&self, channel: usize) -> Mono<'_> {
+        Mono::new(&self.buffer[..CHANNELS], channel, CHANNELS)
+    }
+
+    pub fn left(&self) -> Mono<'_> {
+        self.channel(0)
+    }
+
+    pub fn right(&self) -> Mono<'_> {
+        self.channel(1)
+    }
+
+    /// Copy each channel into its own buffer, returns the number of frames copied
+    pub fn deinterleave<const N: usize>(&self, buffers: &mut [[f32; N]; CHANNELS]) -> usize {
+        let mut frames = 0;
+        for (i, frame) in self.frames().take(N).enumerate() {
+            for (buffer, sample) in buffers.iter_mut().zip(frame.iter()) {
+                buffer[i] = *sample;
+            }
+            frames += 1;
+        }
+        frames
     }
 }
 
-pub struct Output {
+pub struct Output<const CHANNELS: usize = STEREO> {
     index: usize,
     buffer: &'static mut IoBuffer,
+    dither: [Dither; CHANNELS],
 }
 
-impl Output {
-    fn new(buffer: &'static mut IoBuffer) -> Self {
-        Self { index: 0, buffer }
+impl<const CHANNELS: usize> Output<CHANNELS> {
+    /// `Audio` makes its own, this is for processing other buffers and for tests
+    pub fn new(buffer: &'static mut IoBuffer) -> Self {
+        Self {
+            index: 0,
+            buffer,
+            dither: core::array::from_fn(|i| Dither::new(DitherMode::None, i as u32 + 1)),
+        }
+    }
+
+    /// Dither used converting pushed samples to S24, none by default
+    pub fn set_dither(&mut self, mode: DitherMode) {
+        for dither in self.dither.iter_mut() {
+            dither.set_mode(mode);
+        }
     }
 
     fn reset(&mut self) {
         self.index = 0;
     }
 
-    pub fn push(&mut self, data: (f32, f32)) -> Result<(), ()> {
-        if self.index < (BLOCK_SIZE_MAX * 2) {
-            self.buffer[self.index] = S24::from(data.0).into();
-            self.buffer[self.index + 1] = S24::from(data.1).into();
-            self.index += 2;
+    pub fn push_frame(&mut self, frame: Frame<CHANNELS>) -> Result<(), ()> {
+        if self.index < (BLOCK_SIZE_MAX * CHANNELS) {
+            for (i, sample) in frame.iter().enumerate() {
+                self.buffer[self.index + i] = self.dither[i].convert::<S24>(*sample).to_word();
+            }
+            self.index += CHANNELS;
             return Ok(());
         }
         Err(())
     }
+
+    /// Iterator over the frames written so far
+    pub fn frames(&self) -> FrameIterator<'_, CHANNELS> {
+        FrameIterator::new(&self.buffer[..self.index])
+    }
+
+    /// Write one channel of the block, starting from its first frame
+    ///
+    /// Frames no channel has been written to yet start out silent. Writing a channel that doesn't
+    /// exist fails.
+    pub fn channel(&mut self, channel: usize) -> OutputChannel<'_, CHANNELS> {
+        OutputChannel {
+            output: self,
+            channel,
+            frame: 0,
+        }
+    }
+
+    pub fn left(&mut self) -> OutputChannel<'_, CHANNELS> {
+        self.channel(0)
+    }
+
+    pub fn right(&mut self) -> OutputChannel<'_, CHANNELS> {
+        self.channel(1)
+    }
+
+    /// Push the same sample to every channel
+    pub fn push_mono(&mut self, sample: f32) -> Result<(), ()> {
+        self.push_frame([sample; CHANNELS])
+    }
+
+    /// Push the first `frames` samples of each channel's buffer, stops when the output is full
+    pub fn interleave<const N: usize>(
+        &mut self,
+        buffers: &[[f32; N]; CHANNELS],
+        frames: usize,
+    ) -> Result<(), ()> {
+        for i in 0..frames.min(N) {
+            let mut frame = [0.0; CHANNELS];
+            for (sample, buffer) in frame.iter_mut().zip(buffers.iter()) {
+                *sample = buffer[i];
+            }
+            self.push_frame(frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl Output {
+    pub fn push(&mut self, data: (f32, f32)) -> Result<(), ()> {
+        self.push_frame([data.0, data.1])
+    }
+}
+
+/// Writer for one channel of an `Output`, see [`Output::channel`]
+pub struct OutputChannel<'a, const CHANNELS: usize> {
+    output: &'a mut Output<CHANNELS>,
+    channel: usize,
+    frame: usize,
+}
+
+impl<const CHANNELS: usize> OutputChannel<'_, CHANNELS> {
+    /// Write the channel's sample in the next frame, fails once the block is full
+    pub fn push(&mut self, sample: f32) -> Result<(), ()> {
+        if self.channel >= CHANNELS || self.frame >= BLOCK_SIZE_MAX {
+            return Err(());
+        }
+        let output = &mut *self.output;
+        let start = self.frame * CHANNELS;
+        if start >= output.index {
+            output.buffer[start..start + CHANNELS].fill(0);
+            output.index = start + CHANNELS;
+        }
+        output.buffer[start + self.channel] =
+            output.dither[self.channel].convert::<S24>(sample).to_word();
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Write `samples` to consecutive frames, stops when the block is full
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), ()> {
+        for sample in samples {
+            self.push(*sample)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct StereoIterator<'a> {
     index: usize,
+    stride: usize,
     buf: &'a [u32],
 }
 
 impl<'a> StereoIterator<'a> {
-    fn new(buf: &'a [u32]) -> Self {
-        Self { index: 0, buf }
+    fn new(buf: &'a [u32], stride: usize) -> Self {
+        Self {
+            index: 0,
+            stride,
+            buf,
+        }
     }
 }
 
@@ -152,10 +475,10 @@ impl Iterator for StereoIterator<'_> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.buf.len() {
-            self.index += 2;
+            self.index += self.stride;
             Some((
-                S24(self.buf[self.index - 2] as i32).into(),
-                S24(self.buf[self.index - 1] as i32).into(),
+                S24::from_word(self.buf[self.index - self.stride]).to_f32(),
+                S24::from_word(self.buf[self.index - self.stride + 1]).to_f32(),
             ))
         } else {
             None
@@ -163,14 +486,49 @@ impl Iterator for StereoIterator<'_> {
     }
 }
 
+pub struct FrameIterator<'a, const CHANNELS: usize> {
+    index: usize,
+    buf: &'a [u32],
+}
+
+impl<'a, const CHANNELS: usize> FrameIterator<'a, CHANNELS> {
+    fn new(buf: &'a [u32]) -> Self {
+        Self { index: 0, buf }
+    }
+}
+
+impl<const CHANNELS: usize> Iterator for FrameIterator<'_, CHANNELS> {
+    type Item = Frame<CHANNELS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index + CHANNELS <= self.buf.len() {
+            let mut frame = [0.0; CHANNELS];
+            for (i, sample) in frame.iter_mut().enumerate() {
+                *sample = S24::from_word(self.buf[self.index + i]).to_f32();
+            }
+            self.index += CHANNELS;
+            Some(frame)
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterator over one channel of an interleaved buffer
 pub struct Mono<'a> {
     index: usize,
-    buf: &'a [i32],
+    stride: usize,
+    buf: &'a [u32],
 }
 
 impl<'a> Mono<'a> {
-    fn new(buf: &'a [i32]) -> Self {
-        Self { index: 0, buf }
+    fn new(buf: &'a [u32], channel: usize, stride: usize) -> Self {
+        Self {
+            // Start past the end when there is no such channel
+            index: if channel < stride { channel } else { buf.len() },
+            stride,
+            buf,
+        }
     }
 }
 
@@ -179,8 +537,8 @@ impl Iterator for Mono<'_> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.buf.len() {
-            self.index += 2;
-            Some(S24(self.buf[self.index - 1]).into())
+            self.index += self.stride;
+            Some(S24::from_word(self.buf[self.index - self.stride]).to_f32())
         } else {
             None
         }
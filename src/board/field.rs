@@ -0,0 +1,207 @@
+//! Daisy Field, a 16 key keybed, eight knobs behind a CD4051 multiplexer,
+//! two CV outputs, gate in and out, an OLED and MIDI
+use stm32h7xx_hal::adc;
+use stm32h7xx_hal::gpio::{Alternate, Floating, AF5, AF7};
+use stm32h7xx_hal::serial;
+use stm32h7xx_hal::spi;
+use stm32h7xx_hal::stm32;
+use stm32h7xx_hal::timer::Timer;
+
+use crate::audio;
+use crate::cv;
+use crate::display;
+use crate::gpio::*;
+use crate::hid;
+use crate::midi::{MidiMessage, MidiParser};
+use crate::prelude::*;
+use crate::system::{Resources, System};
+
+/// Rate of the interface timer, keys are debounced and one knob is read at this rate
+pub const INTERFACE_RATE_MS: u32 = 1;
+
+pub const KEYS: usize = 16;
+pub const KNOBS: usize = hid::MUX_CHANNELS;
+
+const KEYBOARD_REGISTERS: usize = 2;
+static KEYBOARD_STATE: hid::ShiftRegisterState<KEYBOARD_REGISTERS> = hid::ShiftRegisterState::new();
+
+const MIDI_BAUD: u32 = 31_250;
+
+pub type Knobs = hid::MuxedAnalogInputs<
+    Daisy16<Analog>,
+    Daisy0<Output<PushPull>>,
+    Daisy1<Output<PushPull>>,
+    Daisy2<Output<PushPull>>,
+>;
+pub type GateIn = hid::GateIn<Daisy20<Input<Floating>>>;
+pub type GateOut = hid::GateOut<Daisy17<Output<PushPull>>>;
+pub type FieldDisplay = display::Oled<
+    spi::Spi<stm32::SPI1, u8>,
+    Daisy9<Output<PushPull>>,
+    Daisy30<Output<PushPull>>,
+    Daisy7<Output<PushPull>>,
+>;
+pub type MidiTx = serial::Tx<stm32::USART1>;
+pub type MidiRx = serial::Rx<stm32::USART1>;
+pub type Key = hid::Switch<hid::ShiftRegisterPin<'static, KEYBOARD_REGISTERS>>;
+
+/// Chained CD4021s holding the keybed, keys pull their input low when touched
+type Keyboard = hid::ShiftRegister4021<
+    'static,
+    Daisy28<Output<PushPull>>,
+    Daisy27<Output<PushPull>>,
+    Daisy26<Input<Floating>>,
+    KEYBOARD_REGISTERS,
+>;
+
+type DisplaySpiPins = (Daisy8<Alternate<AF5>>, spi::NoMiso, Daisy10<Alternate<AF5>>);
+type MidiPins = (Daisy13<Alternate<AF7>>, Daisy14<Alternate<AF7>>);
+
+pub struct Field {
+    pub seed_led: SeedLed,
+    /// One knob is read per `process_analog_controls`
+    pub knobs: Knobs,
+    pub keys: [Key; KEYS],
+    pub gate_in: GateIn,
+    pub gate_out: GateOut,
+    /// CV outputs 1 and 2 are DAC channels one and two, calibrated for 0 to 5V
+    pub cv_out: cv::CvOut,
+    pub display: FieldDisplay,
+    pub midi_tx: MidiTx,
+    pub midi_rx: MidiRx,
+    pub adc1: adc::Adc<stm32::ADC1, adc::Enabled>,
+    /// Interface timer, runs every `INTERFACE_RATE_MS`
+    pub timer2: Timer<stm32::TIM2>,
+    keyboard: Keyboard,
+    midi_parser: MidiParser,
+}
+
+impl Field {
+    /// Set up the Field's controls and outputs, returns the Field, its audio interface and the
+    /// unused `Resources`
+    pub fn init(system: System) -> (Field, audio::Audio, Resources) {
+        let (mut gpio, audio, adc1, mut timer2, mut resources) = system.split();
+        timer2.set_freq(INTERFACE_RATE_MS.ms());
+
+        let mut adc1 = adc1.enable();
+        adc1.set_resolution(adc::Resolution::SIXTEENBIT);
+        let adc1_max = adc1.max_sample() as f32;
+
+        let knobs = hid::MuxedAnalogInputs::new(
+            gpio.daisy16.take().unwrap().into_analog(),
+            gpio.daisy0.take().unwrap().into_push_pull_output(),
+            gpio.daisy1.take().unwrap().into_push_pull_output(),
+            gpio.daisy2.take().unwrap().into_push_pull_output(),
+            adc1_max,
+        );
+
+        let keyboard = hid::ShiftRegister4021::new(
+            gpio.daisy28.take().unwrap().into_push_pull_output(),
+            gpio.daisy27.take().unwrap().into_push_pull_output(),
+            gpio.daisy26.take().unwrap().into_floating_input(),
+            &KEYBOARD_STATE,
+        );
+        let keys = core::array::from_fn(|key| {
+            hid::Switch::new(keyboard.pin(key), hid::SwitchType::PullUp)
+        });
+
+        // The gate input stage inverts
+        let gate_in = hid::GateIn::new(gpio.daisy20.take().unwrap().into_floating_input(), true);
+        let gate_out = hid::GateOut::new(
+            gpio.daisy17.take().unwrap().into_push_pull_output(),
+            false,
+            INTERFACE_RATE_MS,
+        );
+
+        let (dac, dac_rec) = resources.dac.take().unwrap();
+        let mut cv_out = cv::CvOut::new(
+            dac,
+            (gpio.daisy23.take().unwrap(), gpio.daisy22.take().unwrap()),
+            dac_rec,
+        );
+        cv_out.set_calibration(cv::Channel::One, cv::Calibration::new(0.0, 5.0));
+        cv_out.set_calibration(cv::Channel::Two, cv::Calibration::new(0.0, 5.0));
+
+        let pins: DisplaySpiPins = (
+            gpio.daisy8.take().unwrap().into_alternate_af5(),
+            spi::NoMiso,
+            gpio.daisy10.take().unwrap().into_alternate_af5(),
+        );
+        let (spi1, spi1_rec) = resources.spi1.take().unwrap();
+        let spi = spi1.spi(pins, spi::MODE_0, 8.mhz(), spi1_rec, &resources.clocks);
+        let mut display = display::Oled::new(
+            spi,
+            gpio.daisy9.take().unwrap().into_push_pull_output(),
+            gpio.daisy30.take().unwrap().into_push_pull_output(),
+            gpio.daisy7.take().unwrap().into_push_pull_output(),
+        );
+        display.init(&mut resources.delay).unwrap();
+
+        let pins: MidiPins = (
+            gpio.daisy13.take().unwrap().into_alternate_af7(),
+            gpio.daisy14.take().unwrap().into_alternate_af7(),
+        );
+        let (usart1, usart1_rec) = resources.usart1.take().unwrap();
+        let (midi_tx, midi_rx) = usart1
+            .serial(pins, MIDI_BAUD.bps(), usart1_rec, &resources.clocks)
+            .unwrap()
+            .split();
+
+        let field = Field {
+            seed_led: gpio.led,
+            knobs,
+            keys,
+            gate_in,
+            gate_out,
+            cv_out,
+            display,
+            midi_tx,
+            midi_rx,
+            adc1,
+            timer2,
+            keyboard,
+            midi_parser: MidiParser::new(),
+        };
+        (field, audio, resources)
+    }
+
+    pub fn process_analog_controls(&mut self) {
+        self.knobs.update(&mut self.adc1);
+    }
+
+    pub fn process_digital_controls(&mut self) {
+        self.keyboard.update();
+        for key in self.keys.iter_mut() {
+            key.update();
+        }
+        self.gate_in.update();
+        self.gate_out.update();
+    }
+
+    pub fn key_pressed(&self, key: usize) -> bool {
+        self.keys[key].is_high()
+    }
+
+    pub fn key_rising(&self, key: usize) -> bool {
+        self.keys[key].is_rising()
+    }
+
+    pub fn key_falling(&self, key: usize) -> bool {
+        self.keys[key].is_falling()
+    }
+
+    /// Set a CV output in volts, 0 to 5V
+    pub fn set_cv_out(&mut self, channel: cv::Channel, volts: f32) {
+        self.cv_out.set_volts(channel, volts);
+    }
+
+    /// Parse any received MIDI bytes, returns the first complete message
+    pub fn read_midi(&mut self) -> Option<MidiMessage> {
+        while let Ok(byte) = self.midi_rx.read() {
+            if let Some(message) = self.midi_parser.parse(byte) {
+                return Some(message);
+            }
+        }
+        None
+    }
+}
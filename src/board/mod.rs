@@ -0,0 +1,5 @@
+//! Board support for Daisy platforms built around the Seed
+pub mod field;
+pub mod patch;
+pub mod petal;
+pub mod pod;
@@ -0,0 +1,158 @@
+//! Daisy Patch, four knob/CV controls, an encoder, gates, an OLED and quad audio
+use stm32h7xx_hal::adc;
+use stm32h7xx_hal::gpio::{Alternate, Floating, AF5};
+use stm32h7xx_hal::spi;
+use stm32h7xx_hal::stm32;
+use stm32h7xx_hal::timer::Timer;
+
+use crate::audio;
+use crate::codec::{self, Codec};
+use crate::display;
+use crate::gpio::*;
+use crate::hid;
+use crate::prelude::*;
+use crate::system::{Resources, System};
+use crate::AUDIO_SAMPLE_HZ;
+
+/// Rate of the interface timer, the encoder is debounced at this rate
+pub const INTERFACE_RATE_MS: u32 = 1;
+
+pub type Ctrl1 = hid::AnalogControl<Daisy15<Analog>>;
+pub type Ctrl2 = hid::AnalogControl<Daisy16<Analog>>;
+pub type Ctrl3 = hid::AnalogControl<Daisy21<Analog>>;
+pub type Ctrl4 = hid::AnalogControl<Daisy18<Analog>>;
+pub type PatchEncoder =
+    hid::Encoder<Daisy12<Input<PullUp>>, Daisy11<Input<PullUp>>, Daisy0<Input<PullUp>>>;
+pub type GateIn1 = hid::GateIn<Daisy20<Input<Floating>>>;
+pub type GateIn2 = hid::GateIn<Daisy19<Input<Floating>>>;
+pub type GateOut = hid::GateOut<Daisy17<Output<PushPull>>>;
+pub type PatchCodec = codec::Ak4556<Daisy29<Output<PushPull>>>;
+pub type PatchDisplay = display::Oled<
+    spi::Spi<stm32::SPI1, u8>,
+    Daisy9<Output<PushPull>>,
+    Daisy30<Output<PushPull>>,
+    Daisy7<Output<PushPull>>,
+>;
+
+type DisplaySpiPins = (Daisy8<Alternate<AF5>>, spi::NoMiso, Daisy10<Alternate<AF5>>);
+
+pub struct Patch {
+    pub seed_led: SeedLed,
+    pub ctrl1: Ctrl1,
+    pub ctrl2: Ctrl2,
+    pub ctrl3: Ctrl3,
+    pub ctrl4: Ctrl4,
+    pub encoder: PatchEncoder,
+    pub gate_in1: GateIn1,
+    pub gate_in2: GateIn2,
+    pub gate_out: GateOut,
+    pub display: PatchDisplay,
+    /// Codec on SAI2 for audio channels 3 and 4
+    pub codec2: PatchCodec,
+    pub adc1: adc::Adc<stm32::ADC1, adc::Enabled>,
+    /// Interface timer, runs every `INTERFACE_RATE_MS`
+    pub timer2: Timer<stm32::TIM2>,
+}
+
+impl Patch {
+    /// Set up the Patch's controls and second codec, returns the Patch, its audio interface and
+    /// the unused `Resources`
+    pub fn init(
+        system: System<{ audio::QUAD }>,
+    ) -> (Patch, audio::Audio<{ audio::QUAD }>, Resources) {
+        let (mut gpio, audio, adc1, mut timer2, mut resources) = system.split();
+        timer2.set_freq(INTERFACE_RATE_MS.ms());
+
+        let mut adc1 = adc1.enable();
+        adc1.set_resolution(adc::Resolution::SIXTEENBIT);
+        let adc1_max = adc1.max_sample() as f32;
+
+        // Knobs and CV inputs are summed by an inverting stage
+        let mut ctrl1 =
+            hid::AnalogControl::new(gpio.daisy15.take().unwrap().into_analog(), adc1_max);
+        let mut ctrl2 =
+            hid::AnalogControl::new(gpio.daisy16.take().unwrap().into_analog(), adc1_max);
+        let mut ctrl3 =
+            hid::AnalogControl::new(gpio.daisy21.take().unwrap().into_analog(), adc1_max);
+        let mut ctrl4 =
+            hid::AnalogControl::new(gpio.daisy18.take().unwrap().into_analog(), adc1_max);
+        ctrl1.set_transform(|x| 1.0 - x);
+        ctrl2.set_transform(|x| 1.0 - x);
+        ctrl3.set_transform(|x| 1.0 - x);
+        ctrl4.set_transform(|x| 1.0 - x);
+
+        let encoder = hid::Encoder::new(
+            gpio.daisy12.take().unwrap().into_pull_up_input(),
+            gpio.daisy11.take().unwrap().into_pull_up_input(),
+            gpio.daisy0.take().unwrap().into_pull_up_input(),
+            hid::SwitchType::PullUp,
+        );
+
+        // The gate input stages invert
+        let gate_in1 = hid::GateIn::new(gpio.daisy20.take().unwrap().into_floating_input(), true);
+        let gate_in2 = hid::GateIn::new(gpio.daisy19.take().unwrap().into_floating_input(), true);
+        let gate_out = hid::GateOut::new(
+            gpio.daisy17.take().unwrap().into_push_pull_output(),
+            false,
+            INTERFACE_RATE_MS,
+        );
+
+        let pins: DisplaySpiPins = (
+            gpio.daisy8.take().unwrap().into_alternate_af5(),
+            spi::NoMiso,
+            gpio.daisy10.take().unwrap().into_alternate_af5(),
+        );
+        let (spi1, spi1_rec) = resources.spi1.take().unwrap();
+        let spi = spi1.spi(pins, spi::MODE_0, 8.mhz(), spi1_rec, &resources.clocks);
+        let mut display = display::Oled::new(
+            spi,
+            gpio.daisy9.take().unwrap().into_push_pull_output(),
+            gpio.daisy30.take().unwrap().into_push_pull_output(),
+            gpio.daisy7.take().unwrap().into_push_pull_output(),
+        );
+        display.init(&mut resources.delay).unwrap();
+
+        // SAI2 is already running, so the codec can come out of reset
+        let mut codec2 = codec::Ak4556::new(gpio.daisy29.take().unwrap().into_push_pull_output());
+        codec2.init(AUDIO_SAMPLE_HZ).unwrap();
+
+        let patch = Patch {
+            seed_led: gpio.led,
+            ctrl1,
+            ctrl2,
+            ctrl3,
+            ctrl4,
+            encoder,
+            gate_in1,
+            gate_in2,
+            gate_out,
+            display,
+            codec2,
+            adc1,
+            timer2,
+        };
+        (patch, audio, resources)
+    }
+
+    pub fn process_analog_controls(&mut self) {
+        if let Ok(data) = self.adc1.read(&mut self.ctrl1.pin) {
+            self.ctrl1.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.ctrl2.pin) {
+            self.ctrl2.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.ctrl3.pin) {
+            self.ctrl3.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.ctrl4.pin) {
+            self.ctrl4.update(data);
+        }
+    }
+
+    pub fn process_digital_controls(&mut self) {
+        self.encoder.update();
+        self.gate_in1.update();
+        self.gate_in2.update();
+        self.gate_out.update();
+    }
+}
@@ -0,0 +1,242 @@
+//! Daisy Petal, six knobs, an expression input, an encoder, four footswitches,
+//! a bank of three toggles and a ring of RGB LEDs driven by two PCA9685s
+use stm32h7xx_hal::adc;
+use stm32h7xx_hal::gpio::{Alternate, Floating, AF4};
+use stm32h7xx_hal::i2c;
+use stm32h7xx_hal::stm32;
+use stm32h7xx_hal::timer::Timer;
+
+use crate::audio;
+use crate::gpio::*;
+use crate::hid;
+use crate::pca9685::Pca9685;
+use crate::prelude::*;
+use crate::system::{Resources, System};
+
+/// Rate of the interface timer, switches are debounced at this rate
+pub const INTERFACE_RATE_MS: u32 = 1;
+
+pub const FOOTSWITCHES: usize = 4;
+pub const TOGGLES: usize = 3;
+pub const RING_LEDS: usize = 8;
+
+const LED_DRIVERS: usize = 2;
+const LED_DRIVER_ADDRESSES: [u8; LED_DRIVERS] = [0x40, 0x41];
+/// Red, green and blue LED driver channels of each ring LED, clockwise from the top
+const RING_LED_CHANNELS: [[usize; 3]; RING_LEDS] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [9, 10, 11],
+    [12, 13, 14],
+    [16, 17, 18],
+    [19, 20, 21],
+    [22, 23, 24],
+];
+const FOOTSWITCH_LED_CHANNELS: [usize; FOOTSWITCHES] = [15, 25, 26, 27];
+
+const SWITCH_REGISTERS: usize = 2;
+static SWITCH_STATE: hid::ShiftRegisterState<SWITCH_REGISTERS> = hid::ShiftRegisterState::new();
+
+/// Shift register bits of the footswitches
+const FOOTSWITCH_BITS: [usize; FOOTSWITCHES] = [0, 1, 2, 3];
+/// Shift register bits of the up and down contacts of each toggle
+const TOGGLE_BITS: [(usize, usize); TOGGLES] = [(4, 5), (6, 7), (8, 9)];
+
+pub type Knob1 = hid::AnalogControl<Daisy16<Analog>>;
+pub type Knob2 = hid::AnalogControl<Daisy19<Analog>>;
+pub type Knob3 = hid::AnalogControl<Daisy17<Analog>>;
+pub type Knob4 = hid::AnalogControl<Daisy20<Analog>>;
+pub type Knob5 = hid::AnalogControl<Daisy18<Analog>>;
+pub type Knob6 = hid::AnalogControl<Daisy21<Analog>>;
+pub type Expression = hid::AnalogControl<Daisy15<Analog>>;
+pub type PetalEncoder =
+    hid::Encoder<Daisy28<Input<PullUp>>, Daisy27<Input<PullUp>>, Daisy14<Input<PullUp>>>;
+pub type LedDriver = Pca9685<i2c::I2c<stm32::I2C1>, LED_DRIVERS>;
+pub type Footswitch = hid::Switch<hid::ShiftRegisterPin<'static, SWITCH_REGISTERS>>;
+
+/// Chained CD4021s holding the footswitches and toggles
+///
+/// All switches pull their input low when closed.
+type SwitchRegister = hid::ShiftRegister4021<
+    'static,
+    Daisy8<Output<PushPull>>,
+    Daisy7<Output<PushPull>>,
+    Daisy9<Input<Floating>>,
+    SWITCH_REGISTERS,
+>;
+
+type I2cPins = (Daisy11<Alternate<AF4>>, Daisy12<Alternate<AF4>>);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TogglePosition {
+    Up,
+    Center,
+    Down,
+}
+
+pub struct Petal {
+    pub seed_led: SeedLed,
+    pub knob1: Knob1,
+    pub knob2: Knob2,
+    pub knob3: Knob3,
+    pub knob4: Knob4,
+    pub knob5: Knob5,
+    pub knob6: Knob6,
+    pub expression: Expression,
+    pub encoder: PetalEncoder,
+    pub footswitches: [Footswitch; FOOTSWITCHES],
+    pub leds: LedDriver,
+    pub adc1: adc::Adc<stm32::ADC1, adc::Enabled>,
+    /// Interface timer, runs every `INTERFACE_RATE_MS`
+    pub timer2: Timer<stm32::TIM2>,
+    switches: SwitchRegister,
+}
+
+impl Petal {
+    /// Set up the Petal's controls and LEDs, returns the Petal, its audio interface and the
+    /// unused `Resources`
+    pub fn init(system: System) -> (Petal, audio::Audio, Resources) {
+        let (mut gpio, audio, adc1, mut timer2, mut resources) = system.split();
+        timer2.set_freq(INTERFACE_RATE_MS.ms());
+
+        let mut adc1 = adc1.enable();
+        adc1.set_resolution(adc::Resolution::SIXTEENBIT);
+        let adc1_max = adc1.max_sample() as f32;
+
+        let knob1 = hid::AnalogControl::new(gpio.daisy16.take().unwrap().into_analog(), adc1_max);
+        let knob2 = hid::AnalogControl::new(gpio.daisy19.take().unwrap().into_analog(), adc1_max);
+        let knob3 = hid::AnalogControl::new(gpio.daisy17.take().unwrap().into_analog(), adc1_max);
+        let knob4 = hid::AnalogControl::new(gpio.daisy20.take().unwrap().into_analog(), adc1_max);
+        let knob5 = hid::AnalogControl::new(gpio.daisy18.take().unwrap().into_analog(), adc1_max);
+        let knob6 = hid::AnalogControl::new(gpio.daisy21.take().unwrap().into_analog(), adc1_max);
+        let expression =
+            hid::AnalogControl::new(gpio.daisy15.take().unwrap().into_analog(), adc1_max);
+
+        let encoder = hid::Encoder::new(
+            gpio.daisy28.take().unwrap().into_pull_up_input(),
+            gpio.daisy27.take().unwrap().into_pull_up_input(),
+            gpio.daisy14.take().unwrap().into_pull_up_input(),
+            hid::SwitchType::PullUp,
+        );
+
+        let switches = hid::ShiftRegister4021::new(
+            gpio.daisy8.take().unwrap().into_push_pull_output(),
+            gpio.daisy7.take().unwrap().into_push_pull_output(),
+            gpio.daisy9.take().unwrap().into_floating_input(),
+            &SWITCH_STATE,
+        );
+        let footswitches = core::array::from_fn(|i| {
+            hid::Switch::new(switches.pin(FOOTSWITCH_BITS[i]), hid::SwitchType::PullUp)
+        });
+
+        let pins: I2cPins = (
+            gpio.daisy11
+                .take()
+                .unwrap()
+                .into_alternate_af4()
+                .set_open_drain(),
+            gpio.daisy12
+                .take()
+                .unwrap()
+                .into_alternate_af4()
+                .set_open_drain(),
+        );
+        let (i2c1, i2c1_rec) = resources.i2c1.take().unwrap();
+        let i2c = i2c1.i2c(pins, 1.mhz(), i2c1_rec, &resources.clocks);
+        let mut leds = Pca9685::new(i2c, LED_DRIVER_ADDRESSES);
+        leds.init().unwrap();
+
+        let petal = Petal {
+            seed_led: gpio.led,
+            knob1,
+            knob2,
+            knob3,
+            knob4,
+            knob5,
+            knob6,
+            expression,
+            encoder,
+            footswitches,
+            leds,
+            adc1,
+            timer2,
+            switches,
+        };
+        (petal, audio, resources)
+    }
+
+    pub fn process_analog_controls(&mut self) {
+        if let Ok(data) = self.adc1.read(&mut self.knob1.pin) {
+            self.knob1.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob2.pin) {
+            self.knob2.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob3.pin) {
+            self.knob3.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob4.pin) {
+            self.knob4.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob5.pin) {
+            self.knob5.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob6.pin) {
+            self.knob6.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.expression.pin) {
+            self.expression.update(data);
+        }
+    }
+
+    pub fn process_digital_controls(&mut self) {
+        self.encoder.update();
+
+        self.switches.update();
+        for footswitch in self.footswitches.iter_mut() {
+            footswitch.update();
+        }
+    }
+
+    pub fn footswitch_pressed(&self, footswitch: usize) -> bool {
+        self.footswitches[footswitch].is_high()
+    }
+
+    pub fn footswitch_rising(&self, footswitch: usize) -> bool {
+        self.footswitches[footswitch].is_rising()
+    }
+
+    pub fn footswitch_falling(&self, footswitch: usize) -> bool {
+        self.footswitches[footswitch].is_falling()
+    }
+
+    pub fn toggle_position(&self, toggle: usize) -> TogglePosition {
+        let (up, down) = TOGGLE_BITS[toggle];
+        if !SWITCH_STATE.is_high(up) {
+            TogglePosition::Up
+        } else if !SWITCH_STATE.is_high(down) {
+            TogglePosition::Down
+        } else {
+            TogglePosition::Center
+        }
+    }
+
+    pub fn set_ring_led(&mut self, led: usize, red: f32, green: f32, blue: f32) {
+        let [r, g, b] = RING_LED_CHANNELS[led];
+        self.leds.set_led(r, red);
+        self.leds.set_led(g, green);
+        self.leds.set_led(b, blue);
+    }
+
+    pub fn set_footswitch_led(&mut self, footswitch: usize, brightness: f32) {
+        self.leds
+            .set_led(FOOTSWITCH_LED_CHANNELS[footswitch], brightness);
+    }
+
+    /// Send changed LED values to the drivers, blocks on I2C so call this at a
+    /// low rate or from `idle`
+    pub fn update_leds(&mut self) {
+        self.leds.flush().unwrap();
+    }
+}
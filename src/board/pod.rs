@@ -0,0 +1,121 @@
+//! Daisy Pod, two knobs, two buttons, an encoder and two RGB LEDs
+use stm32h7xx_hal::adc;
+use stm32h7xx_hal::stm32;
+use stm32h7xx_hal::timer::Timer;
+
+use crate::audio;
+use crate::gpio::*;
+use crate::hid;
+use crate::prelude::*;
+use crate::system::{Resources, System};
+
+/// Rate of the interface timer, buttons and the encoder are debounced at this rate
+pub const INTERFACE_RATE_MS: u32 = 1;
+/// LED PWM steps, with a 1ms interface rate the LEDs refresh at 100Hz
+pub const LED_RESOLUTION: u32 = 10;
+
+pub type Knob1 = hid::AnalogControl<Daisy21<Analog>>;
+pub type Knob2 = hid::AnalogControl<Daisy15<Analog>>;
+pub type Button1 = hid::Switch<Daisy27<Input<PullUp>>>;
+pub type Button2 = hid::Switch<Daisy28<Input<PullUp>>>;
+pub type PodEncoder =
+    hid::Encoder<Daisy26<Input<PullUp>>, Daisy25<Input<PullUp>>, Daisy13<Input<PullUp>>>;
+pub type Led1 =
+    hid::RgbLed<Daisy20<Output<PushPull>>, Daisy19<Output<PushPull>>, Daisy18<Output<PushPull>>>;
+pub type Led2 =
+    hid::RgbLed<Daisy17<Output<PushPull>>, Daisy24<Output<PushPull>>, Daisy23<Output<PushPull>>>;
+
+pub struct Pod {
+    pub seed_led: SeedLed,
+    pub knob1: Knob1,
+    pub knob2: Knob2,
+    pub button1: Button1,
+    pub button2: Button2,
+    pub encoder: PodEncoder,
+    pub led1: Led1,
+    pub led2: Led2,
+    pub adc1: adc::Adc<stm32::ADC1, adc::Enabled>,
+    /// Interface timer, runs every `INTERFACE_RATE_MS`
+    pub timer2: Timer<stm32::TIM2>,
+}
+
+impl Pod {
+    /// Set up the Pod's controls, returns the Pod, its audio interface and the unused `Resources`
+    pub fn init(system: System) -> (Pod, audio::Audio, Resources) {
+        let (mut gpio, audio, adc1, mut timer2, resources) = system.split();
+        timer2.set_freq(INTERFACE_RATE_MS.ms());
+
+        let mut adc1 = adc1.enable();
+        adc1.set_resolution(adc::Resolution::SIXTEENBIT);
+        let adc1_max = adc1.max_sample() as f32;
+
+        let knob1 = hid::AnalogControl::new(gpio.daisy21.take().unwrap().into_analog(), adc1_max);
+        let knob2 = hid::AnalogControl::new(gpio.daisy15.take().unwrap().into_analog(), adc1_max);
+
+        let button1 = hid::Switch::new(
+            gpio.daisy27.take().unwrap().into_pull_up_input(),
+            hid::SwitchType::PullUp,
+        );
+        let button2 = hid::Switch::new(
+            gpio.daisy28.take().unwrap().into_pull_up_input(),
+            hid::SwitchType::PullUp,
+        );
+
+        let encoder = hid::Encoder::new(
+            gpio.daisy26.take().unwrap().into_pull_up_input(),
+            gpio.daisy25.take().unwrap().into_pull_up_input(),
+            gpio.daisy13.take().unwrap().into_pull_up_input(),
+            hid::SwitchType::PullUp,
+        );
+
+        // The RGB LEDs are common anode
+        let led1 = hid::RgbLed::new(
+            gpio.daisy20.take().unwrap().into_push_pull_output(),
+            gpio.daisy19.take().unwrap().into_push_pull_output(),
+            gpio.daisy18.take().unwrap().into_push_pull_output(),
+            true,
+            LED_RESOLUTION,
+        );
+        let led2 = hid::RgbLed::new(
+            gpio.daisy17.take().unwrap().into_push_pull_output(),
+            gpio.daisy24.take().unwrap().into_push_pull_output(),
+            gpio.daisy23.take().unwrap().into_push_pull_output(),
+            true,
+            LED_RESOLUTION,
+        );
+
+        let pod = Pod {
+            seed_led: gpio.led,
+            knob1,
+            knob2,
+            button1,
+            button2,
+            encoder,
+            led1,
+            led2,
+            adc1,
+            timer2,
+        };
+        (pod, audio, resources)
+    }
+
+    pub fn process_analog_controls(&mut self) {
+        if let Ok(data) = self.adc1.read(&mut self.knob1.pin) {
+            self.knob1.update(data);
+        }
+        if let Ok(data) = self.adc1.read(&mut self.knob2.pin) {
+            self.knob2.update(data);
+        }
+    }
+
+    pub fn process_digital_controls(&mut self) {
+        self.button1.update();
+        self.button2.update();
+        self.encoder.update();
+    }
+
+    pub fn update_leds(&mut self) {
+        self.led1.update();
+        self.led2.update();
+    }
+}
@@ -0,0 +1,198 @@
+//! Audio codec abstraction
+//!
+//! The [`Codec`] trait covers the control side of an audio codec: reset, initialisation,
+//! sample rate changes, mute and volume. The audio data itself always goes through SAI,
+//! so any codec wired to SAI1 or SAI2 can be driven through this trait.
+use stm32h7xx_hal::hal::blocking::i2c::Write;
+use stm32h7xx_hal::hal::digital::v2::OutputPin;
+use stm32h7xx_hal::time::Hertz;
+
+use crate::system::Timeout;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error<E> {
+    /// Error from the underlying pin or bus
+    Bus(E),
+    /// The codec has no way to perform the requested operation
+    Unsupported,
+    /// The codec cannot run at the requested sample rate
+    InvalidSampleRate,
+}
+
+pub trait Codec {
+    type Error;
+
+    /// Hardware reset of the codec, leaves it in its power on state
+    fn reset(&mut self) -> Result<(), Error<Self::Error>>;
+
+    /// Reset and configure the codec to run at `sample_rate`
+    fn init(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>>;
+
+    fn set_sample_rate(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>>;
+
+    fn mute(&mut self) -> Result<(), Error<Self::Error>> {
+        Err(Error::Unsupported)
+    }
+
+    fn unmute(&mut self) -> Result<(), Error<Self::Error>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Set output volume, 0.0 is silent and 1.0 is unity gain
+    fn set_volume(&mut self, _volume: f32) -> Result<(), Error<Self::Error>> {
+        Err(Error::Unsupported)
+    }
+}
+
+/// AK4556, the codec on the Daisy Seed and the second codec of the Daisy Patch
+///
+/// The AK4556 has no control interface, it only has a reset pin and detects
+/// the sample rate from the MCLK/LRCK ratio.
+pub struct Ak4556<T> {
+    reset: T,
+}
+
+impl<T> Ak4556<T>
+where
+    T: OutputPin,
+{
+    pub fn new(reset: T) -> Self {
+        Self { reset }
+    }
+
+    pub fn free(self) -> T {
+        self.reset
+    }
+}
+
+impl<T> Codec for Ak4556<T>
+where
+    T: OutputPin,
+{
+    type Error = T::Error;
+
+    fn reset(&mut self) -> Result<(), Error<Self::Error>> {
+        self.reset.set_low().map_err(Error::Bus)?;
+        Timeout::from_millis(5).wait();
+        self.reset.set_high().map_err(Error::Bus)
+    }
+
+    fn init(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>> {
+        self.set_sample_rate(sample_rate)?;
+        self.reset()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>> {
+        // Normal, double and quad speed modes
+        match sample_rate.0 {
+            8_000..=192_000 => Ok(()),
+            _ => Err(Error::InvalidSampleRate),
+        }
+    }
+}
+
+const WM8731_ADDRESS: u8 = 0x1a;
+
+// Registers
+const WM8731_LEFT_LINE_IN: u8 = 0x00;
+const WM8731_RIGHT_LINE_IN: u8 = 0x01;
+const WM8731_LEFT_HP_OUT: u8 = 0x02;
+const WM8731_RIGHT_HP_OUT: u8 = 0x03;
+const WM8731_ANALOG_PATH: u8 = 0x04;
+const WM8731_DIGITAL_PATH: u8 = 0x05;
+const WM8731_POWER_DOWN: u8 = 0x06;
+const WM8731_INTERFACE_FORMAT: u8 = 0x07;
+const WM8731_SAMPLING: u8 = 0x08;
+const WM8731_ACTIVE: u8 = 0x09;
+const WM8731_RESET: u8 = 0x0f;
+
+// Register values
+const WM8731_LINE_IN_0DB: u16 = 0x017;
+const WM8731_HP_OUT_MUTE: u16 = 0x030;
+const WM8731_HP_OUT_0DB: u16 = 0x079;
+const WM8731_HP_OUT_BOTH: u16 = 0x100;
+const WM8731_DAC_SELECT: u16 = 0x012;
+const WM8731_DAC_MUTE: u16 = 0x008;
+// Everything on except the microphone, oscillator and clock output
+const WM8731_POWER_ON: u16 = 0x062;
+// I2S, 24 bit, slave
+const WM8731_I2S_24_BIT: u16 = 0x00a;
+
+/// WM8731, the codec on the Daisy Seed 1.1, controlled over I2C
+pub struct Wm8731<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Wm8731<I2C>
+where
+    I2C: Write<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u16) -> Result<(), Error<E>> {
+        // 7 bit register address followed by 9 bits of data
+        let bytes = [(reg << 1) | ((value >> 8) as u8 & 0x01), value as u8];
+        self.i2c.write(WM8731_ADDRESS, &bytes).map_err(Error::Bus)
+    }
+}
+
+impl<I2C, E> Codec for Wm8731<I2C>
+where
+    I2C: Write<Error = E>,
+{
+    type Error = E;
+
+    fn reset(&mut self) -> Result<(), Error<Self::Error>> {
+        self.write_reg(WM8731_RESET, 0)
+    }
+
+    fn init(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>> {
+        self.reset()?;
+        self.write_reg(WM8731_LEFT_LINE_IN, WM8731_LINE_IN_0DB)?;
+        self.write_reg(WM8731_RIGHT_LINE_IN, WM8731_LINE_IN_0DB)?;
+        self.write_reg(WM8731_LEFT_HP_OUT, WM8731_HP_OUT_BOTH | WM8731_HP_OUT_0DB)?;
+        self.write_reg(WM8731_ANALOG_PATH, WM8731_DAC_SELECT)?;
+        self.write_reg(WM8731_DIGITAL_PATH, 0)?;
+        self.write_reg(WM8731_POWER_DOWN, WM8731_POWER_ON)?;
+        self.write_reg(WM8731_INTERFACE_FORMAT, WM8731_I2S_24_BIT)?;
+        self.set_sample_rate(sample_rate)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hertz) -> Result<(), Error<Self::Error>> {
+        // Normal mode, 256fs, SR bits for an MCLK of 256fs
+        let sr = match sample_rate.0 {
+            8_000 => 0x03,
+            32_000 => 0x06,
+            48_000 => 0x00,
+            96_000 => 0x07,
+            _ => return Err(Error::InvalidSampleRate),
+        };
+        // Interface must be inactive while changing the sampling control
+        self.write_reg(WM8731_ACTIVE, 0)?;
+        self.write_reg(WM8731_SAMPLING, sr << 2)?;
+        self.write_reg(WM8731_ACTIVE, 1)
+    }
+
+    fn mute(&mut self) -> Result<(), Error<Self::Error>> {
+        self.write_reg(WM8731_DIGITAL_PATH, WM8731_DAC_MUTE)
+    }
+
+    fn unmute(&mut self) -> Result<(), Error<Self::Error>> {
+        self.write_reg(WM8731_DIGITAL_PATH, 0)
+    }
+
+    /// Set the headphone output volume, the line output has a fixed level
+    fn set_volume(&mut self, volume: f32) -> Result<(), Error<Self::Error>> {
+        let volume = volume.clamp(0.0, 1.0);
+        // 1dB steps from mute up to 0dB
+        let steps = (WM8731_HP_OUT_0DB - WM8731_HP_OUT_MUTE) as f32;
+        let value = WM8731_HP_OUT_MUTE + (volume * steps) as u16;
+        self.write_reg(WM8731_LEFT_HP_OUT, WM8731_HP_OUT_BOTH | value)
+    }
+}
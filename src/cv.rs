@@ -0,0 +1,300 @@
+//! CV outputs on the two DAC channels, Daisy23 (PA4) and Daisy22 (PA5)
+//!
+//! Values are given either normalised to the DAC range or in volts, which go through a
+//! per channel [`Calibration`] for the op-amp stage following the DAC.
+//!
+//! Buffers of DAC codes can also be streamed by DMA1, one value per TIM6 update, see
+//! [`CvOut::enable_streams`]. DMA1 can't reach the DTCM, where statics end up by default, so
+//! stream buffers have to be `const` data in flash or statics placed in `.sram1_bss`.
+use stm32h7xx_hal::dac;
+use stm32h7xx_hal::rcc;
+use stm32h7xx_hal::rcc::ResetEnable;
+use stm32h7xx_hal::stm32;
+
+use crate::gpio::*;
+use crate::prelude::*;
+
+pub const DAC_MAX: u16 = 4095;
+
+/// DMA1 stream feeding each channel, streams 0 to 3 share the LISR/LIFCR flag registers
+const DMA_STREAMS: [usize; 2] = [0, 1];
+/// Offsets of each stream's flags in LIFCR
+const DMA_FLAG_OFFSETS: [u32; 2] = [0, 6];
+const DMA_FLAGS: u32 = 0x3d;
+/// DMAMUX1 request lines `dac_ch1_dma` and `dac_ch2_dma`
+const DMA_REQUESTS: [u32; 2] = [67, 68];
+
+/// DAC_CR bits for channel 1, channel 2's are 16 bits higher
+const DAC_CR_EN: u32 = 1 << 0;
+const DAC_CR_TEN: u32 = 1 << 1;
+const DAC_CR_TSEL_MASK: u32 = 0xf << 2;
+/// `dac_chx_trg5`, TIM6 TRGO
+const DAC_CR_TSEL_TIM6: u32 = 5 << 2;
+const DAC_CR_DMAEN: u32 = 1 << 12;
+
+/// TIM6_CR2 master mode, the update event is TRGO
+const TIM_CR2_MMS_UPDATE: u32 = 0b010 << 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Channel {
+    /// DAC channel 1 on Daisy23
+    One,
+    /// DAC channel 2 on Daisy22
+    Two,
+}
+
+/// Linear map from volts at the output jack to DAC codes, `code = volts * scale + offset`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Calibration {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Calibration {
+    /// Ideal output stage where the DAC range spans `min_volts` to `max_volts`
+    pub fn new(min_volts: f32, max_volts: f32) -> Self {
+        let scale = DAC_MAX as f32 / (max_volts - min_volts);
+        Self {
+            scale,
+            offset: -min_volts * scale,
+        }
+    }
+
+    /// Calibrate from two DAC codes and the voltages measured at the jack for each
+    pub fn from_measurements(code_a: u16, volts_a: f32, code_b: u16, volts_b: f32) -> Self {
+        let scale = (code_b as f32 - code_a as f32) / (volts_b - volts_a);
+        Self {
+            scale,
+            offset: code_a as f32 - volts_a * scale,
+        }
+    }
+
+    pub fn to_code(&self, volts: f32) -> u16 {
+        (volts * self.scale + self.offset).clamp(0.0, DAC_MAX as f32) as u16
+    }
+}
+
+impl Default for Calibration {
+    /// The DAC pins themselves, 0 to 3.3V
+    fn default() -> Self {
+        Self::new(0.0, 3.3)
+    }
+}
+
+/// DMA and trigger timer for streamed output
+struct Streams {
+    dma1: stm32::DMA1,
+    dmamux1: stm32::DMAMUX1,
+    tim6: stm32::TIM6,
+}
+
+pub struct CvOut {
+    out1: dac::C1<stm32::DAC, dac::Enabled>,
+    out2: dac::C2<stm32::DAC, dac::Enabled>,
+    calibration: [Calibration; 2],
+    streams: Option<Streams>,
+}
+
+impl CvOut {
+    pub fn new(
+        dac: stm32::DAC,
+        pins: (Daisy23<Analog>, Daisy22<Analog>),
+        rec: rcc::rec::Dac12,
+    ) -> Self {
+        let (out1, out2) = dac.dac(pins, rec);
+        Self {
+            out1: out1.enable(),
+            out2: out2.enable(),
+            calibration: [Calibration::default(); 2],
+            streams: None,
+        }
+    }
+
+    pub fn set_calibration(&mut self, channel: Channel, calibration: Calibration) {
+        self.calibration[channel as usize] = calibration;
+    }
+
+    pub fn calibration(&self, channel: Channel) -> Calibration {
+        self.calibration[channel as usize]
+    }
+
+    /// Set the output in the range [0.0, 1.0] of the DAC, ignoring calibration
+    ///
+    /// Stops any stream playing on `channel`.
+    pub fn set_normalized(&mut self, channel: Channel, value: f32) {
+        let code = (value.clamp(0.0, 1.0) * DAC_MAX as f32) as u16;
+        self.write(channel, code);
+    }
+
+    /// Set the output voltage at the jack, clamped to what the DAC can reach
+    ///
+    /// Stops any stream playing on `channel`.
+    pub fn set_volts(&mut self, channel: Channel, volts: f32) {
+        let code = self.calibration[channel as usize].to_code(volts);
+        self.write(channel, code);
+    }
+
+    /// Convert voltages to DAC codes for `stream`, using `channel`'s calibration
+    pub fn volts_to_codes(&self, channel: Channel, volts: &[f32], codes: &mut [u16]) {
+        let calibration = self.calibration[channel as usize];
+        for (code, volts) in codes.iter_mut().zip(volts) {
+            *code = calibration.to_code(*volts);
+        }
+    }
+
+    /// Start TIM6 at `rate_hz` to trigger DAC conversions, streams on both channels run at this rate
+    pub fn enable_streams(
+        &mut self,
+        dma: (stm32::DMA1, stm32::DMAMUX1, rcc::rec::Dma1),
+        timer: (stm32::TIM6, rcc::rec::Tim6),
+        rate_hz: u32,
+        clocks: &rcc::CoreClocks,
+    ) {
+        let (dma1, dmamux1, dma1_rec) = dma;
+        let (tim6, tim6_rec) = timer;
+        dma1_rec.enable().reset();
+        tim6_rec.enable().reset();
+
+        // The update event is TRGO, one DAC conversion per timer period
+        unsafe { tim6.cr2.write(|w| w.bits(TIM_CR2_MMS_UPDATE)) };
+        self.streams = Some(Streams {
+            dma1,
+            dmamux1,
+            tim6,
+        });
+        self.set_stream_rate(rate_hz, clocks);
+    }
+
+    /// Change the rate of both channels' streams
+    ///
+    /// TIM6 runs from the APB1 timer kernel clock, so rates that don't divide it are rounded.
+    /// Panics if `rate_hz` is zero or above half the timer clock, or `enable_streams` wasn't
+    /// called.
+    pub fn set_stream_rate(&mut self, rate_hz: u32, clocks: &rcc::CoreClocks) {
+        assert!(rate_hz > 0);
+        // The counter stops with ARR at 0, so it needs at least two ticks per update
+        let ticks = clocks.timx_ker_ck().0 / rate_hz;
+        assert!(ticks >= 2, "stream rate above half the timer clock");
+        let tim6 = &self
+            .streams
+            .as_ref()
+            .expect("enable_streams must be called first")
+            .tim6;
+
+        // Both PSC and ARR are 16 bit, prescale only as much as needed
+        let prescaler = (ticks - 1) / (1 << 16);
+        let reload = ticks / (prescaler + 1) - 1;
+        tim6.cr1.modify(|_, w| w.cen().clear_bit());
+        unsafe {
+            tim6.psc.write(|w| w.bits(prescaler));
+            tim6.arr.write(|w| w.bits(reload));
+        }
+        tim6.egr.write(|w| w.ug().set_bit());
+        tim6.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// DMA `codes` to `channel`, one per TIM6 update until the buffer ends, or forever if `looping`
+    ///
+    /// Replaces any stream already playing on `channel`. The last code is held once a
+    /// stream ends. Panics if `enable_streams` wasn't called.
+    pub fn stream(&mut self, channel: Channel, codes: &'static [u16], looping: bool) {
+        self.stop_stream(channel);
+        let streams = self
+            .streams
+            .as_ref()
+            .expect("enable_streams must be called first");
+        let n = DMA_STREAMS[channel as usize];
+        let stream = &streams.dma1.st[n];
+        let dac = unsafe { &*stm32::DAC::ptr() };
+        let target = match channel {
+            Channel::One => &dac.dhr12r1 as *const _ as u32,
+            Channel::Two => &dac.dhr12r2 as *const _ as u32,
+        };
+
+        unsafe {
+            streams.dmamux1.ccr[n].write(|w| w.bits(DMA_REQUESTS[channel as usize]));
+            streams
+                .dma1
+                .lifcr
+                .write(|w| w.bits(DMA_FLAGS << DMA_FLAG_OFFSETS[channel as usize]));
+            stream.par.write(|w| w.bits(target));
+            stream.m0ar.write(|w| w.bits(codes.as_ptr() as u32));
+            stream.ndtr.write(|w| w.bits(codes.len() as u32));
+        }
+        // Direct mode, half words to the right aligned 12 bit holding register
+        stream.fcr.reset();
+        stream.cr.write(|w| {
+            w.dir()
+                .memory_to_peripheral()
+                .msize()
+                .bits16()
+                .psize()
+                .bits16()
+                .minc()
+                .set_bit()
+                .circ()
+                .bit(looping)
+                .pl()
+                .high()
+        });
+        stream.cr.modify(|_, w| w.en().set_bit());
+
+        set_trigger(channel, DAC_CR_TEN | DAC_CR_TSEL_TIM6 | DAC_CR_DMAEN);
+    }
+
+    /// Stop the stream on `channel`, the output holds its last value
+    pub fn stop_stream(&mut self, channel: Channel) {
+        let streams = match &self.streams {
+            Some(streams) => streams,
+            None => return,
+        };
+        let stream = &streams.dma1.st[DMA_STREAMS[channel as usize]];
+        stream.cr.modify(|_, w| w.en().clear_bit());
+        while stream.cr.read().en().bit_is_set() {}
+
+        // Back to software writes
+        set_trigger(channel, 0);
+    }
+
+    /// A stream is playing on `channel`, false once a non-looping stream has ended
+    pub fn is_streaming(&self, channel: Channel) -> bool {
+        match &self.streams {
+            Some(streams) => streams.dma1.st[DMA_STREAMS[channel as usize]]
+                .cr
+                .read()
+                .en()
+                .bit_is_set(),
+            None => false,
+        }
+    }
+
+    fn write(&mut self, channel: Channel, code: u16) {
+        // Also after a stream ended, the DAC would otherwise still wait for TIM6
+        if is_triggered(channel) {
+            self.stop_stream(channel);
+        }
+        match channel {
+            Channel::One => self.out1.set_value(code),
+            Channel::Two => self.out2.set_value(code),
+        }
+    }
+}
+
+fn is_triggered(channel: Channel) -> bool {
+    let dac = unsafe { &*stm32::DAC::ptr() };
+    dac.cr.read().bits() & (DAC_CR_TEN << (16 * channel as u32)) != 0
+}
+
+/// Set the trigger and DMA bits of `channel` in DAC_CR, they can't change while it's enabled
+fn set_trigger(channel: Channel, bits: u32) {
+    let dac = unsafe { &*stm32::DAC::ptr() };
+    let shift = 16 * channel as u32;
+    let mask = (DAC_CR_TEN | DAC_CR_TSEL_MASK | DAC_CR_DMAEN) << shift;
+    let enable = DAC_CR_EN << shift;
+    unsafe {
+        dac.cr.modify(|r, w| w.bits(r.bits() & !enable));
+        dac.cr
+            .modify(|r, w| w.bits((r.bits() & !mask) | (bits << shift)));
+        dac.cr.modify(|r, w| w.bits(r.bits() | enable));
+    }
+}
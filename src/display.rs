@@ -0,0 +1,119 @@
+//! Driver for the 128x64 SSD1309/SSD1306 OLED displays used on Daisy boards
+//!
+//! Drawing happens in a local frame buffer which is sent to the display with `flush`.
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::OutputPin;
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+const PAGES: usize = HEIGHT / 8;
+const BUFFER_SIZE: usize = WIDTH * PAGES;
+
+const INIT_SEQUENCE: [u8; 25] = [
+    0xae, // Display off
+    0xd5, 0x80, // Clock divide ratio
+    0xa8, 0x3f, // Multiplex ratio, 64 lines
+    0xd3, 0x00, // No display offset
+    0x40, // Start line 0
+    0x8d, 0x14, // Charge pump on (SSD1306 only)
+    0x20, 0x00, // Horizontal addressing
+    0xa1, // Segment remap
+    0xc8, // COM scan direction decrement
+    0xda, 0x12, // COM pins
+    0x81, 0x8f, // Contrast
+    0xd9, 0x22, // Precharge
+    0xdb, 0x34, // VCOMH deselect level
+    0xa4, // Display follows RAM
+    0xa6, // Normal, not inverted
+    0xaf, // Display on
+];
+
+pub struct Oled<SPI, DC, RST, CS> {
+    spi: SPI,
+    dc: DC,
+    reset: RST,
+    cs: CS,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl<SPI, DC, RST, CS> Oled<SPI, DC, RST, CS>
+where
+    SPI: Write<u8>,
+    DC: OutputPin,
+    RST: OutputPin,
+    CS: OutputPin,
+{
+    pub fn new(spi: SPI, dc: DC, reset: RST, cs: CS) -> Self {
+        Self {
+            spi,
+            dc,
+            reset,
+            cs,
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    /// Reset and configure the display, then clear it
+    pub fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), SPI::Error> {
+        self.cs.set_high().ok().unwrap();
+        self.reset.set_low().ok().unwrap();
+        delay.delay_ms(1);
+        self.reset.set_high().ok().unwrap();
+        delay.delay_ms(1);
+
+        self.command(&INIT_SEQUENCE)?;
+        self.clear();
+        self.flush()
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(false);
+    }
+
+    pub fn fill(&mut self, on: bool) {
+        let value = if on { 0xff } else { 0x00 };
+        for byte in self.buffer.iter_mut() {
+            *byte = value;
+        }
+    }
+
+    /// Pixels outside of the display are ignored
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x >= WIDTH || y >= HEIGHT {
+            return;
+        }
+        let byte = &mut self.buffer[x + (y / 8) * WIDTH];
+        if on {
+            *byte |= 1 << (y % 8);
+        } else {
+            *byte &= !(1 << (y % 8));
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        if x >= WIDTH || y >= HEIGHT {
+            return false;
+        }
+        self.buffer[x + (y / 8) * WIDTH] & (1 << (y % 8)) != 0
+    }
+
+    /// Send the frame buffer to the display
+    pub fn flush(&mut self) -> Result<(), SPI::Error> {
+        self.command(&[0x21, 0, (WIDTH - 1) as u8, 0x22, 0, (PAGES - 1) as u8])?;
+
+        self.dc.set_high().ok().unwrap();
+        self.cs.set_low().ok().unwrap();
+        let result = self.spi.write(&self.buffer);
+        self.cs.set_high().ok().unwrap();
+        result
+    }
+
+    fn command(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        self.dc.set_low().ok().unwrap();
+        self.cs.set_low().ok().unwrap();
+        let result = self.spi.write(bytes);
+        self.cs.set_high().ok().unwrap();
+        result
+    }
+}
@@ -0,0 +1,53 @@
+//! Interrupt driven digital inputs
+//!
+//! Each pin is routed through SYSCFG to the EXTI line matching its pin number, so only one
+//! port can use a given line. Lines 5 to 9 share the `EXTI9_5` interrupt and lines 10 to 15
+//! share `EXTI15_10`, use [`InterruptInput::is_pending`] to tell inputs on a shared interrupt apart.
+use stm32h7xx_hal::gpio::ExtiPin;
+use stm32h7xx_hal::hal::digital::v2::InputPin;
+use stm32h7xx_hal::stm32;
+
+pub use stm32h7xx_hal::gpio::Edge;
+
+/// A pin set up as an EXTI interrupt source
+pub struct InterruptInput<T> {
+    pin: T,
+}
+
+impl<T> InterruptInput<T>
+where
+    T: ExtiPin + InputPin,
+    <T as InputPin>::Error: core::fmt::Debug,
+{
+    /// Route `pin` to its EXTI line and unmask it, interrupting on `edge`
+    pub fn new(mut pin: T, edge: Edge, exti: &mut stm32::EXTI, syscfg: &mut stm32::SYSCFG) -> Self {
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, edge);
+        pin.enable_interrupt(exti);
+        Self { pin }
+    }
+
+    /// The interrupt was triggered by this input
+    pub fn is_pending(&self) -> bool {
+        self.pin.check_interrupt()
+    }
+
+    /// Clear the pending interrupt, must be called in the handler or it will fire again
+    pub fn clear(&mut self) {
+        self.pin.clear_interrupt_pending_bit();
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.pin.is_high().unwrap()
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.pin.is_low().unwrap()
+    }
+
+    /// Mask the EXTI line and return the pin
+    pub fn free(mut self, exti: &mut stm32::EXTI) -> T {
+        self.pin.disable_interrupt(exti);
+        self.pin
+    }
+}
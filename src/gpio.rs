@@ -4,7 +4,6 @@ use stm32h7xx_hal::gpio::gpioc::PC7;
 // use stm32h7xx_hal::hal::digital::v2::InputPin;
 #[allow(unused_imports)]
 use stm32h7xx_hal::gpio::{Alternate, Analog, Input, Output, PullUp, PushPull};
-use stm32h7xx_hal::hal::digital::v2::OutputPin;
 
 pub use gpio::gpioa::PA0 as Daisy25;
 pub use gpio::gpioa::PA1 as Daisy24;
@@ -39,12 +38,13 @@ pub use gpio::gpiog::PG11 as Daisy8;
 pub use gpio::gpiog::PG9 as Daisy27;
 
 pub type SeedLed = PC7<Output<PushPull>>;
+pub type SeedCodec = codec::Ak4556<gpio::gpiob::PB11<Output<PushPull>>>;
 
-use crate::*;
+use crate::codec::{self, Codec};
 
 pub struct GPIO {
     pub led: SeedLed,
-    codec: gpio::gpiob::PB11<Output<PushPull>>,
+    pub codec: SeedCodec,
     pub daisy0: Option<gpio::gpiob::PB12<Analog>>,
     pub daisy1: Option<gpio::gpioc::PC11<Analog>>,
     pub daisy2: Option<gpio::gpioc::PC10<Analog>>,
@@ -87,7 +87,7 @@ impl GPIO {
         gpiog: gpio::gpiog::Parts,
     ) -> GPIO {
         let led = gpioc.pc7.into_push_pull_output();
-        let codec = gpiob.pb11.into_push_pull_output();
+        let codec = codec::Ak4556::new(gpiob.pb11.into_push_pull_output());
         GPIO {
             led,
             codec,
@@ -126,8 +126,6 @@ impl GPIO {
     }
 
     pub fn reset_codec(&mut self) {
-        self.codec.set_low().unwrap();
-        delay_ms(5);
-        self.codec.set_high().unwrap();
+        self.codec.reset().unwrap();
     }
 }
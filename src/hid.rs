@@ -1,9 +1,22 @@
 //! Interface abstractions for switches, potentiometer, etc.
+//!
+//! Nothing here depends on the Daisy hardware besides `GateIn::update`, so the rest builds
+//! and is tested on the host with `--no-default-features`.
+#[cfg(feature = "hal")]
 #[allow(unused_imports)]
 use stm32h7xx_hal::gpio::{Analog, Input, Output, PullDown, PullUp, PushPull};
-use stm32h7xx_hal::hal::digital::v2::{InputPin, OutputPin};
+
+use embedded_hal::adc::{Channel, OneShot};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+#[cfg(feature = "hal")]
+use cortex_m::peripheral::DWT;
+
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use debouncr::{debounce_4, Debouncer, Edge, Repeat4};
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
 pub type TransformFn = fn(f32) -> f32;
@@ -193,6 +206,99 @@ impl<T> AnalogControl<T> {
     }
 }
 
+pub const MUX_CHANNELS: usize = 8;
+
+/// Eight `AnalogControl`s read through a CD4051 8:1 multiplexer on one ADC pin
+///
+/// One channel is read per `update`, after which the next channel is selected so
+/// the multiplexer output has until the next update to settle.
+pub struct MuxedAnalogInputs<P, S0, S1, S2> {
+    pin: P,
+    select0: S0,
+    select1: S1,
+    select2: S2,
+    controls: [AnalogControl<()>; MUX_CHANNELS],
+    channel: usize,
+    settle_ticks: u32,
+    ticks: u32,
+}
+
+impl<P, S0, S1, S2> MuxedAnalogInputs<P, S0, S1, S2>
+where
+    S0: OutputPin,
+    S1: OutputPin,
+    S2: OutputPin,
+{
+    pub fn new(pin: P, select0: S0, select1: S1, select2: S2, scale: f32) -> Self {
+        let mut mux = Self {
+            pin,
+            select0,
+            select1,
+            select2,
+            controls: core::array::from_fn(|_| AnalogControl::new((), scale)),
+            channel: 0,
+            settle_ticks: 0,
+            ticks: 0,
+        };
+        mux.select(0);
+        mux
+    }
+
+    /// Extra updates to wait after switching channels before reading
+    pub fn set_settle_ticks(&mut self, settle_ticks: u32) {
+        self.settle_ticks = settle_ticks;
+    }
+
+    /// Read the selected channel and move on to the next one
+    ///
+    /// A failed read leaves the channel selected so it's read again on the next update.
+    pub fn update<ADC, A>(&mut self, adc: &mut A)
+    where
+        P: Channel<ADC>,
+        A: OneShot<ADC, u32, P>,
+    {
+        if self.ticks < self.settle_ticks {
+            self.ticks += 1;
+            return;
+        }
+
+        if let Ok(data) = adc.read(&mut self.pin) {
+            self.controls[self.channel].update(data);
+            self.select((self.channel + 1) % MUX_CHANNELS);
+        }
+    }
+
+    /// Channel that will be read on the next update
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    pub fn control(&self, channel: usize) -> &AnalogControl<()> {
+        &self.controls[channel]
+    }
+
+    pub fn control_mut(&mut self, channel: usize) -> &mut AnalogControl<()> {
+        &mut self.controls[channel]
+    }
+
+    pub fn get_value(&self, channel: usize) -> f32 {
+        self.controls[channel].get_value()
+    }
+
+    fn select(&mut self, channel: usize) {
+        self.channel = channel;
+        self.ticks = 0;
+        set_pin(&mut self.select0, channel & 0x01 != 0);
+        set_pin(&mut self.select1, channel & 0x02 != 0);
+        set_pin(&mut self.select2, channel & 0x04 != 0);
+    }
+}
+
+fn set_pin<T: OutputPin>(pin: &mut T, high: bool) {
+    let result = if high { pin.set_high() } else { pin.set_low() };
+    result.ok().expect("failed to drive a mux select pin");
+}
+
 pub struct Led<T> {
     pin: T,
     /// inverts the brightness level
@@ -250,3 +356,531 @@ where
         }
     }
 }
+
+pub struct RgbLed<R, G, B> {
+    pub red: Led<R>,
+    pub green: Led<G>,
+    pub blue: Led<B>,
+}
+
+impl<R, G, B> RgbLed<R, G, B>
+where
+    R: OutputPin,
+    G: OutputPin,
+    B: OutputPin,
+{
+    pub fn new(red: R, green: G, blue: B, invert: bool, resolution: u32) -> Self {
+        Self {
+            red: Led::new(red, invert, resolution),
+            green: Led::new(green, invert, resolution),
+            blue: Led::new(blue, invert, resolution),
+        }
+    }
+
+    pub fn set_color(&mut self, red: f32, green: f32, blue: f32) {
+        self.red.set_brightness(red);
+        self.green.set_brightness(green);
+        self.blue.set_brightness(blue);
+    }
+
+    pub fn update(&mut self) {
+        self.red.update();
+        self.green.update();
+        self.blue.update();
+    }
+}
+
+/// Quadrature encoder with a push switch
+pub struct Encoder<A, B, C> {
+    a: A,
+    b: B,
+    /// Last 8 samples of each phase, newest in the lowest bit
+    a_state: u8,
+    b_state: u8,
+    increment: i32,
+    pub switch: Switch<C>,
+}
+
+impl<A, B, C> Encoder<A, B, C>
+where
+    A: InputPin,
+    <A as InputPin>::Error: core::fmt::Debug,
+    B: InputPin,
+    <B as InputPin>::Error: core::fmt::Debug,
+    C: InputPin,
+    <C as InputPin>::Error: core::fmt::Debug,
+{
+    /// Encoder phases are assumed to be pulled up
+    pub fn new(a: A, b: B, click: C, switch_type: SwitchType) -> Self {
+        Self {
+            a,
+            b,
+            a_state: 0xff,
+            b_state: 0xff,
+            increment: 0,
+            switch: Switch::new(click, switch_type),
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.a_state = (self.a_state << 1) | self.a.is_high().unwrap() as u8;
+        self.b_state = (self.b_state << 1) | self.b.is_high().unwrap() as u8;
+
+        // A detent is a falling edge on one phase while the other is low
+        self.increment = if self.a_state & 0x03 == 0x02 && self.b_state & 0x03 == 0x00 {
+            1
+        } else if self.b_state & 0x03 == 0x02 && self.a_state & 0x03 == 0x00 {
+            -1
+        } else {
+            0
+        };
+
+        self.switch.update();
+    }
+
+    /// Steps turned since the last update, 1 clockwise, -1 counter clockwise
+    pub fn increment(&self) -> i32 {
+        self.increment
+    }
+}
+
+/// Chained 74HC595 serial in, parallel out shift registers with N devices
+///
+/// Output `n` is bit `n % 8` of device `n / 8`, device 0 is the one connected to the MCU.
+/// Outputs can be set directly, or given a brightness and driven by software PWM like `Led`.
+pub struct ShiftRegister595<D, C, L, const N: usize> {
+    data: D,
+    clock: C,
+    latch: L,
+    state: [u8; N],
+    dirty: bool,
+    /// Outputs driven by PWM, one bit per output
+    pwm_outputs: [u8; N],
+    brightness: [[f32; 8]; N],
+    resolution: u32,
+    pwm: f32,
+}
+
+impl<D, C, L, const N: usize> ShiftRegister595<D, C, L, N>
+where
+    D: OutputPin,
+    C: OutputPin,
+    L: OutputPin,
+{
+    /// All outputs start low, `resolution` is the number of PWM brightness levels
+    pub fn new(data: D, clock: C, latch: L, resolution: u32) -> Self {
+        Self {
+            data,
+            clock,
+            latch,
+            state: [0; N],
+            dirty: true,
+            pwm_outputs: [0; N],
+            brightness: [[0.0; 8]; N],
+            resolution,
+            pwm: 0.0,
+        }
+    }
+
+    pub fn outputs(&self) -> usize {
+        N * 8
+    }
+
+    /// Set an output high or low, this stops PWM on it
+    pub fn set_output(&mut self, output: usize, high: bool) {
+        let (device, bit) = (output / 8, output % 8);
+        self.pwm_outputs[device] &= !(1 << bit);
+        self.write_bit(device, bit, high);
+    }
+
+    pub fn set(&mut self, output: usize) {
+        self.set_output(output, true);
+    }
+
+    pub fn clear(&mut self, output: usize) {
+        self.set_output(output, false);
+    }
+
+    pub fn get(&self, output: usize) -> bool {
+        self.state[output / 8] & (1 << (output % 8)) != 0
+    }
+
+    /// Drive an output by PWM at `value` brightness, applied by `update`
+    pub fn set_brightness(&mut self, output: usize, value: f32) {
+        let (device, bit) = (output / 8, output % 8);
+        self.pwm_outputs[device] |= 1 << bit;
+        // Bias for slower transitions in the low brightness range, same as `Led`
+        self.brightness[device][bit] = value * value;
+    }
+
+    /// Advance the PWM of brightness driven outputs and flush
+    pub fn update(&mut self) {
+        self.pwm += 1.0 / self.resolution as f32;
+        if self.pwm > 1.0 {
+            self.pwm -= 1.0;
+        }
+
+        for device in 0..N {
+            for bit in 0..8 {
+                if self.pwm_outputs[device] & (1 << bit) != 0 {
+                    let high = self.brightness[device][bit] > self.pwm;
+                    self.write_bit(device, bit, high);
+                }
+            }
+        }
+
+        self.flush();
+    }
+
+    /// Shift out and latch the outputs, does nothing if no output changed
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        // The last device's outputs are shifted in first, most significant bit first
+        for device in (0..N).rev() {
+            for bit in (0..8).rev() {
+                set_pin(&mut self.data, self.state[device] & (1 << bit) != 0);
+                self.clock.set_high().ok().unwrap();
+                self.clock.set_low().ok().unwrap();
+            }
+        }
+        self.latch.set_high().ok().unwrap();
+        self.latch.set_low().ok().unwrap();
+
+        self.dirty = false;
+    }
+
+    fn write_bit(&mut self, device: usize, bit: usize, high: bool) {
+        let state = if high {
+            self.state[device] | (1 << bit)
+        } else {
+            self.state[device] & !(1 << bit)
+        };
+        if state != self.state[device] {
+            self.state[device] = state;
+            self.dirty = true;
+        }
+    }
+}
+
+/// Input levels read from chained CD4021s, shared by the register and its pins
+///
+/// Usually a `static`, so pins can be handed to `Switch`es living in other resources.
+pub struct ShiftRegisterState<const N: usize> {
+    bits: [AtomicU8; N],
+}
+
+impl<const N: usize> ShiftRegisterState<N> {
+    // Only used to initialise `bits`, each element is a fresh atomic
+    #[allow(clippy::declare_interior_mutable_const)]
+    const LOW: AtomicU8 = AtomicU8::new(0);
+
+    pub const fn new() -> Self {
+        Self {
+            bits: [Self::LOW; N],
+        }
+    }
+
+    /// Virtual input pin for parallel input `input`, see `ShiftRegister4021` for numbering
+    pub fn pin(&self, input: usize) -> ShiftRegisterPin<'_, N> {
+        assert!(input < N * 8);
+        ShiftRegisterPin { state: self, input }
+    }
+
+    pub fn is_high(&self, input: usize) -> bool {
+        self.bits[input / 8].load(Ordering::Relaxed) & (1 << (input % 8)) != 0
+    }
+
+    fn store(&self, device: usize, bits: u8) {
+        self.bits[device].store(bits, Ordering::Relaxed);
+    }
+}
+
+impl<const N: usize> Default for ShiftRegisterState<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One input of a `ShiftRegister4021`, holds the level read by the last `update`
+pub struct ShiftRegisterPin<'a, const N: usize> {
+    state: &'a ShiftRegisterState<N>,
+    input: usize,
+}
+
+impl<const N: usize> InputPin for ShiftRegisterPin<'_, N> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.state.is_high(self.input))
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.state.is_high(self.input))
+    }
+}
+
+/// Chained CD4021 parallel in, serial out shift registers with N devices
+///
+/// Input `n` is parallel input P`n % 8 + 1` of device `n / 8`, device 0 is the one
+/// connected to the MCU. Call `update` every interface tick before updating any
+/// `Switch` built on its pins.
+pub struct ShiftRegister4021<'a, C, L, D, const N: usize> {
+    clock: C,
+    load: L,
+    data: D,
+    state: &'a ShiftRegisterState<N>,
+}
+
+impl<'a, C, L, D, const N: usize> ShiftRegister4021<'a, C, L, D, N>
+where
+    C: OutputPin,
+    L: OutputPin,
+    D: InputPin,
+    <D as InputPin>::Error: core::fmt::Debug,
+{
+    pub fn new(clock: C, load: L, data: D, state: &'a ShiftRegisterState<N>) -> Self {
+        Self {
+            clock,
+            load,
+            data,
+            state,
+        }
+    }
+
+    pub fn pin(&self, input: usize) -> ShiftRegisterPin<'a, N> {
+        self.state.pin(input)
+    }
+
+    pub fn update(&mut self) {
+        // Parallel load, then P8 of device 0 is shifted out first
+        self.load.set_high().ok().unwrap();
+        self.load.set_low().ok().unwrap();
+
+        for device in 0..N {
+            let mut bits = 0;
+            for bit in (0..8).rev() {
+                if self.data.is_high().unwrap() {
+                    bits |= 1 << bit;
+                }
+                self.clock.set_high().ok().unwrap();
+                self.clock.set_low().ok().unwrap();
+            }
+            self.state.store(device, bits);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KeyEvent {
+    Pressed(usize),
+    Released(usize),
+}
+
+/// Scanned button grid, each key is debounced like `Switch`
+///
+/// Rows are driven low one at a time and the columns, pulled up, read low for pressed keys.
+/// Every key needs a diode towards its row so there is no ghosting to correct for.
+/// Key `n` is on row `n / COLS` and column `n % COLS`.
+pub struct KeyMatrix<R, C, const ROWS: usize, const COLS: usize> {
+    rows: [R; ROWS],
+    cols: [C; COLS],
+    keys: [[Debouncer<u8, Repeat4>; COLS]; ROWS],
+    pressed: [[bool; COLS]; ROWS],
+    released: [[bool; COLS]; ROWS],
+}
+
+impl<R, C, const ROWS: usize, const COLS: usize> KeyMatrix<R, C, ROWS, COLS>
+where
+    R: OutputPin,
+    C: InputPin,
+    <C as InputPin>::Error: core::fmt::Debug,
+{
+    pub fn new(mut rows: [R; ROWS], cols: [C; COLS]) -> Self {
+        for row in rows.iter_mut() {
+            row.set_high().ok().unwrap();
+        }
+        Self {
+            rows,
+            cols,
+            keys: core::array::from_fn(|_| core::array::from_fn(|_| debounce_4())),
+            pressed: [[false; COLS]; ROWS],
+            released: [[false; COLS]; ROWS],
+        }
+    }
+
+    pub fn keys(&self) -> usize {
+        ROWS * COLS
+    }
+
+    /// Scan the whole matrix, call once per interface tick
+    pub fn update(&mut self) {
+        for (row, pin) in self.rows.iter_mut().enumerate() {
+            pin.set_low().ok().unwrap();
+            for (col, input) in self.cols.iter().enumerate() {
+                let is_pressed = input.is_low().unwrap();
+                let edge = self.keys[row][col].update(is_pressed);
+                self.pressed[row][col] = matches!(edge, Some(Edge::Rising));
+                self.released[row][col] = matches!(edge, Some(Edge::Falling));
+            }
+            pin.set_high().ok().unwrap();
+        }
+    }
+
+    pub fn is_pressed(&self, key: usize) -> bool {
+        self.keys[key / COLS][key % COLS].is_high()
+    }
+
+    /// Keys pressed or released by the last update
+    pub fn events(&self) -> impl Iterator<Item = KeyEvent> + '_ {
+        (0..ROWS * COLS).filter_map(move |key| {
+            let (row, col) = (key / COLS, key % COLS);
+            if self.pressed[row][col] {
+                Some(KeyEvent::Pressed(key))
+            } else if self.released[row][col] {
+                Some(KeyEvent::Released(key))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Gate or trigger input, sampled on every update without debouncing
+///
+/// Edges are timestamped with the DWT cycle counter, which `System::init` enables.
+pub struct GateIn<T> {
+    pin: T,
+    /// Set for inverting input stages, where a high gate reads low
+    invert: bool,
+    state: bool,
+    rising: bool,
+    falling: bool,
+    timestamp: u32,
+}
+
+impl<T> GateIn<T>
+where
+    T: InputPin,
+    <T as InputPin>::Error: core::fmt::Debug,
+{
+    /// Starts at the pin's current level, so a gate that is already high doesn't report a rising edge
+    pub fn new(pin: T, invert: bool) -> Self {
+        let state = pin.is_high().unwrap() != invert;
+        Self {
+            pin,
+            invert,
+            state,
+            rising: false,
+            falling: false,
+            timestamp: 0,
+        }
+    }
+
+    #[cfg(feature = "hal")]
+    pub fn update(&mut self) {
+        self.update_at(DWT::get_cycle_count());
+    }
+
+    /// Update with an explicit timestamp, e.g. one taken at the start of an interrupt
+    pub fn update_at(&mut self, timestamp: u32) {
+        let state = self.pin.is_high().unwrap() != self.invert;
+        self.rising = state && !self.state;
+        self.falling = !state && self.state;
+        if self.rising || self.falling {
+            self.timestamp = timestamp;
+        }
+        self.state = state;
+    }
+
+    /// Gate is high as of the last update
+    pub fn is_high(&self) -> bool {
+        self.state
+    }
+
+    /// Gate went high on the last update
+    pub fn is_rising(&self) -> bool {
+        self.rising
+    }
+
+    /// Gate went low on the last update
+    pub fn is_falling(&self) -> bool {
+        self.falling
+    }
+
+    /// Cycle count of the most recent edge
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+}
+
+/// Gate or trigger output
+///
+/// Triggers are timed by counting calls to `update`, which should run at the `update_rate_ms` passed to `new`.
+pub struct GateOut<T> {
+    pin: T,
+    /// Set for inverting output stages, where driving the pin high sends the gate low
+    invert: bool,
+    update_rate_ms: u32,
+    state: bool,
+    /// Updates left until a trigger ends
+    remaining: u32,
+}
+
+impl<T> GateOut<T>
+where
+    T: OutputPin,
+{
+    /// Panics if `update_rate_ms` is zero
+    pub fn new(pin: T, invert: bool, update_rate_ms: u32) -> Self {
+        assert!(update_rate_ms > 0, "update rate must be non-zero");
+        let mut gate = Self {
+            pin,
+            invert,
+            update_rate_ms,
+            state: true,
+            remaining: 0,
+        };
+        gate.set(false);
+        gate
+    }
+
+    /// Set the gate, cancels any running trigger
+    pub fn set(&mut self, state: bool) {
+        self.remaining = 0;
+        self.write(state);
+    }
+
+    /// Set the gate high for `duration_ms`, rounded up to the update rate
+    pub fn trigger(&mut self, duration_ms: u32) {
+        let updates =
+            duration_ms / self.update_rate_ms + (duration_ms % self.update_rate_ms != 0) as u32;
+        self.remaining = updates.max(1);
+        self.write(true);
+    }
+
+    /// Ends triggers once their duration has passed
+    pub fn update(&mut self) {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                self.write(false);
+            }
+        }
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.state
+    }
+
+    fn write(&mut self, state: bool) {
+        if state != self.invert {
+            self.pin.set_high().ok().unwrap();
+        } else {
+            self.pin.set_low().ok().unwrap();
+        }
+        self.state = state;
+    }
+}
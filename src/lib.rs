@@ -1,31 +1,62 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 
 // #[macro_use(singleton)]
 // extern crate cortex_m;
 
+#[cfg(feature = "hal")]
 use cortex_m::asm::delay as delay_cycles;
 
+#[cfg(feature = "hal")]
 use stm32h7xx_hal::time::{Hertz, MegaHertz};
 
 pub const MILLI: u32 = 1_000;
 pub const AUDIO_FRAME_RATE_HZ: u32 = 1_000;
 pub const AUDIO_BLOCK_SIZE: u16 = 48;
-pub const AUDIO_SAMPLE_HZ: Hertz = Hertz(48_000);
+pub const AUDIO_SAMPLE_RATE: u32 = 48_000;
+#[cfg(feature = "hal")]
+pub const AUDIO_SAMPLE_HZ: Hertz = Hertz(AUDIO_SAMPLE_RATE);
+#[cfg(feature = "hal")]
 pub const CLOCK_RATE_HZ: Hertz = Hertz(480_000_000_u32);
 
+#[cfg(feature = "hal")]
 pub const MILICYCLES: u32 = CLOCK_RATE_HZ.0 / MILLI;
 
+#[cfg(feature = "hal")]
 pub type FrameTimer = stm32h7xx_hal::timer::Timer<stm32h7xx_hal::stm32::TIM2>;
 
 pub mod audio;
+#[cfg(feature = "hal")]
+pub mod board;
+#[cfg(feature = "hal")]
+pub mod codec;
+#[cfg(feature = "hal")]
+pub mod cv;
+pub mod display;
+#[cfg(feature = "hal")]
+pub mod exti;
+#[cfg(feature = "hal")]
 pub mod gpio;
 pub mod hid;
+#[cfg(feature = "hal")]
 pub mod logger;
+pub mod midi;
+pub mod pca9685;
+#[cfg(feature = "hal")]
 pub mod prelude;
+pub mod sample;
+pub mod scheduler;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "hal")]
 pub mod system;
+pub mod wav;
 
-// Delay for ms, note if interrupts are active delay time will extend
+/// Delay for ms, note if interrupts are active delay time will extend
+///
+/// Assumes the core runs at `CLOCK_RATE_HZ`.
+#[cfg(feature = "hal")]
+#[deprecated(note = "use system::Delay, which follows the configured clocks")]
 pub fn delay_ms(ms: u32) {
     delay_cycles(ms * MILICYCLES);
 }
@@ -0,0 +1,147 @@
+//! MIDI byte stream parser
+//!
+//! Handles running status and real time messages interleaved with other messages.
+//! System exclusive and system common messages are skipped.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        control: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// 14 bit value, 8192 is centered
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+pub struct MidiParser {
+    status: Option<u8>,
+    data: [u8; 2],
+    index: usize,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self {
+            status: None,
+            data: [0; 2],
+            index: 0,
+        }
+    }
+
+    /// Feed one byte, returns a message once it is complete
+    pub fn parse(&mut self, byte: u8) -> Option<MidiMessage> {
+        if byte >= 0xf8 {
+            // Real time messages don't affect running status
+            return match byte {
+                0xf8 => Some(MidiMessage::Clock),
+                0xfa => Some(MidiMessage::Start),
+                0xfb => Some(MidiMessage::Continue),
+                0xfc => Some(MidiMessage::Stop),
+                _ => None,
+            };
+        }
+
+        if byte & 0x80 != 0 {
+            self.index = 0;
+            // System messages cancel running status
+            self.status = if byte < 0xf0 { Some(byte) } else { None };
+            return None;
+        }
+
+        let status = self.status?;
+        self.data[self.index] = byte;
+        self.index += 1;
+        if self.index < data_length(status) {
+            return None;
+        }
+        self.index = 0;
+
+        let channel = status & 0x0f;
+        let [data0, data1] = self.data;
+        let message = match status & 0xf0 {
+            // Note on with zero velocity is a note off
+            0x90 if data1 == 0 => MidiMessage::NoteOff {
+                channel,
+                note: data0,
+                velocity: 0,
+            },
+            0x80 => MidiMessage::NoteOff {
+                channel,
+                note: data0,
+                velocity: data1,
+            },
+            0x90 => MidiMessage::NoteOn {
+                channel,
+                note: data0,
+                velocity: data1,
+            },
+            0xa0 => MidiMessage::PolyPressure {
+                channel,
+                note: data0,
+                pressure: data1,
+            },
+            0xb0 => MidiMessage::ControlChange {
+                channel,
+                control: data0,
+                value: data1,
+            },
+            0xc0 => MidiMessage::ProgramChange {
+                channel,
+                program: data0,
+            },
+            0xd0 => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data0,
+            },
+            _ => MidiMessage::PitchBend {
+                channel,
+                value: ((data1 as u16) << 7) | data0 as u16,
+            },
+        };
+        Some(message)
+    }
+}
+
+impl Default for MidiParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn data_length(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
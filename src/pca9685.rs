@@ -0,0 +1,115 @@
+//! Driver for chained PCA9685 16 channel PWM LED drivers sharing one I2C bus
+//!
+//! Brightness is buffered and only sent to a device when one of its channels changed.
+use embedded_hal::blocking::i2c::Write;
+
+pub const CHANNELS: usize = 16;
+
+const MODE1: u8 = 0x00;
+const MODE2: u8 = 0x01;
+const LED0_ON_L: u8 = 0x06;
+
+// Register auto increment
+const MODE1_AI: u8 = 0x20;
+// Outputs inverted for LEDs wired to the supply, totem pole drive
+const MODE2_INVRT_OUTDRV: u8 = 0x14;
+
+const PWM_MAX: u16 = 4095;
+
+pub struct Pca9685<I2C, const N: usize> {
+    i2c: I2C,
+    addresses: [u8; N],
+    pwm: [[u16; CHANNELS]; N],
+    dirty: [bool; N],
+}
+
+impl<I2C, E, const N: usize> Pca9685<I2C, N>
+where
+    I2C: Write<Error = E>,
+{
+    /// `addresses` are the 7 bit I2C addresses of each device in order
+    pub fn new(i2c: I2C, addresses: [u8; N]) -> Self {
+        Self {
+            i2c,
+            addresses,
+            pwm: [[0; CHANNELS]; N],
+            dirty: [true; N],
+        }
+    }
+
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    /// Configure all devices and turn every LED off
+    pub fn init(&mut self) -> Result<(), E> {
+        for address in self.addresses.iter() {
+            self.i2c.write(*address, &[MODE1, MODE1_AI])?;
+            self.i2c.write(*address, &[MODE2, MODE2_INVRT_OUTDRV])?;
+        }
+        self.set_all(0.0);
+        self.flush()
+    }
+
+    /// Set brightness for channel `led` in the range [0.0, 1.0]
+    ///
+    /// Channels are numbered across devices, channel 16 is the first channel of the second device.
+    /// Panics if `led` is past the last channel of the last device.
+    pub fn set_led(&mut self, led: usize, brightness: f32) {
+        let device = led / CHANNELS;
+        let channel = led % CHANNELS;
+        let value = brightness_to_pwm(brightness);
+        if self.pwm[device][channel] != value {
+            self.pwm[device][channel] = value;
+            self.dirty[device] = true;
+        }
+    }
+
+    pub fn set_all(&mut self, brightness: f32) {
+        for led in 0..N * CHANNELS {
+            self.set_led(led, brightness);
+        }
+    }
+
+    /// Current PWM value of `led` in the range [0, 4095]
+    ///
+    /// Panics if `led` is past the last channel of the last device.
+    pub fn get_pwm(&self, led: usize) -> u16 {
+        self.pwm[led / CHANNELS][led % CHANNELS]
+    }
+
+    /// Write every device whose channels changed since the last flush
+    pub fn flush(&mut self) -> Result<(), E> {
+        for device in 0..N {
+            if !self.dirty[device] {
+                continue;
+            }
+            // Register address followed by ON_L, ON_H, OFF_L, OFF_H for each channel
+            let mut bytes = [0u8; 1 + CHANNELS * 4];
+            bytes[0] = LED0_ON_L;
+            for (channel, pwm) in self.pwm[device].iter().enumerate() {
+                let offset = 1 + channel * 4;
+                let (on, off) = match *pwm {
+                    // Full off
+                    0 => (0, 0x1000),
+                    // Full on
+                    PWM_MAX => (0x1000, 0),
+                    pwm => (0, pwm),
+                };
+                bytes[offset] = on as u8;
+                bytes[offset + 1] = (on >> 8) as u8;
+                bytes[offset + 2] = off as u8;
+                bytes[offset + 3] = (off >> 8) as u8;
+            }
+            self.i2c.write(self.addresses[device], &bytes)?;
+            self.dirty[device] = false;
+        }
+        Ok(())
+    }
+}
+
+/// Square the brightness so steps look even to the eye
+fn brightness_to_pwm(brightness: f32) -> u16 {
+    let brightness = brightness.clamp(0.0, 1.0);
+    (brightness * brightness * PWM_MAX as f32) as u16
+}
@@ -0,0 +1,288 @@
+//! Sample formats and conversion to and from f32
+//!
+//! Integer formats are carried in the `u32` words used by the SAI and its buffers, with the
+//! sample in the low bits. Conversions from f32 clip to [`FBIPMIN`, `FBIPMAX`].
+//!
+//! The slice conversions are meant for block processing, they avoid bounds checks and
+//! sign extend with shifts rather than branches.
+
+/// Largest f32 sample, just below 1.0 so it stays in range for every integer format
+pub const FBIPMAX: f32 = 0.999985;
+pub const FBIPMIN: f32 = -FBIPMAX;
+
+const F32_TO_S16_SCALE: f32 = 32768.0; // 2 ** 15
+const S16_TO_F32_SCALE: f32 = 1.0 / F32_TO_S16_SCALE;
+const F32_TO_S24_SCALE: f32 = 8388608.0; // 2 ** 23
+const S24_TO_F32_SCALE: f32 = 1.0 / F32_TO_S24_SCALE;
+const F32_TO_S32_SCALE: f32 = 2147483648.0; // 2 ** 31
+const S32_TO_F32_SCALE: f32 = 1.0 / F32_TO_S32_SCALE;
+
+pub trait Sample: Copy {
+    /// Size of one quantisation step as an f32, zero for float formats
+    const LSB: f32;
+
+    /// Convert from f32 in the range [-1.0, 1.0], out of range values are clipped
+    fn from_f32(x: f32) -> Self;
+
+    fn to_f32(self) -> f32;
+
+    /// From a word as read from the SAI
+    fn from_word(word: u32) -> Self;
+
+    /// To a word as sent to the SAI
+    fn to_word(self) -> u32;
+}
+
+fn clip(x: f32) -> f32 {
+    x.clamp(FBIPMIN, FBIPMAX)
+}
+
+/// 16 bit signed sample
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct S16(pub i16);
+
+impl Sample for S16 {
+    const LSB: f32 = S16_TO_F32_SCALE;
+
+    fn from_f32(x: f32) -> Self {
+        S16((clip(x) * F32_TO_S16_SCALE) as i16)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.0 as f32 * S16_TO_F32_SCALE
+    }
+
+    fn from_word(word: u32) -> Self {
+        S16(word as i16)
+    }
+
+    fn to_word(self) -> u32 {
+        self.0 as u16 as u32
+    }
+}
+
+/// 24 bit signed sample, sign extended in an i32
+///
+/// Values built directly from a raw 24 bit word, e.g. `S24(word as i32)`, still convert
+/// correctly, `to_f32` only looks at the low 24 bits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct S24(pub i32);
+
+impl Sample for S24 {
+    const LSB: f32 = S24_TO_F32_SCALE;
+
+    fn from_f32(x: f32) -> Self {
+        S24((clip(x) * F32_TO_S24_SCALE) as i32)
+    }
+
+    fn to_f32(self) -> f32 {
+        ((self.0 << 8) >> 8) as f32 * S24_TO_F32_SCALE
+    }
+
+    /// Only the low 24 bits of `word` are used
+    fn from_word(word: u32) -> Self {
+        S24(((word << 8) as i32) >> 8)
+    }
+
+    fn to_word(self) -> u32 {
+        self.0 as u32 & 0x00ff_ffff
+    }
+}
+
+/// Only the low 24 bits of `x` are used, sign extended from bit 23
+impl From<i32> for S24 {
+    fn from(x: i32) -> S24 {
+        S24::from_word(x as u32)
+    }
+}
+
+impl From<u32> for S24 {
+    fn from(x: u32) -> S24 {
+        S24::from_word(x)
+    }
+}
+
+impl From<S24> for i32 {
+    fn from(x: S24) -> i32 {
+        x.0
+    }
+}
+
+impl From<S24> for u32 {
+    fn from(x: S24) -> u32 {
+        x.to_word()
+    }
+}
+
+impl From<f32> for S24 {
+    fn from(x: f32) -> S24 {
+        S24::from_f32(x)
+    }
+}
+
+impl From<S24> for f32 {
+    fn from(x: S24) -> f32 {
+        x.to_f32()
+    }
+}
+
+/// 32 bit signed sample
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct S32(pub i32);
+
+impl Sample for S32 {
+    const LSB: f32 = S32_TO_F32_SCALE;
+
+    fn from_f32(x: f32) -> Self {
+        S32((clip(x) * F32_TO_S32_SCALE) as i32)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.0 as f32 * S32_TO_F32_SCALE
+    }
+
+    fn from_word(word: u32) -> Self {
+        S32(word as i32)
+    }
+
+    fn to_word(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+/// Float samples pass through unclipped, words hold the f32 bit pattern
+impl Sample for f32 {
+    const LSB: f32 = 0.0;
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_word(word: u32) -> Self {
+        f32::from_bits(word)
+    }
+
+    fn to_word(self) -> u32 {
+        self.to_bits()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DitherMode {
+    /// Plain conversion, truncating towards zero
+    None,
+    /// Triangular dither of plus or minus one step, then rounding
+    Tpdf,
+    /// TPDF dither with first order error feedback, moving the noise up in frequency
+    TpdfShaped,
+}
+
+/// Dithered conversion from f32 for one channel
+///
+/// Uses a xorshift32 generator, each channel should get its own `Dither` with its own seed
+/// so the noise isn't correlated between channels.
+#[derive(Debug, Copy, Clone)]
+pub struct Dither {
+    mode: DitherMode,
+    state: u32,
+    /// Quantisation error of the last sample, for noise shaping
+    error: f32,
+}
+
+impl Dither {
+    /// `seed` must not be zero
+    pub fn new(mode: DitherMode, seed: u32) -> Self {
+        Self {
+            mode,
+            state: if seed == 0 { 1 } else { seed },
+            error: 0.0,
+        }
+    }
+
+    pub fn mode(&self) -> DitherMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DitherMode) {
+        self.mode = mode;
+        self.error = 0.0;
+    }
+
+    /// Convert `x` to `S`, float formats are never dithered
+    pub fn convert<S: Sample>(&mut self, x: f32) -> S {
+        if self.mode == DitherMode::None || S::LSB == 0.0 {
+            return S::from_f32(x);
+        }
+        let target = if self.mode == DitherMode::TpdfShaped {
+            x - self.error
+        } else {
+            x
+        };
+        let noise = (self.uniform() + self.uniform()) * S::LSB;
+        // Offset by half a step so the truncating conversion rounds
+        let half = 0.5 * S::LSB;
+        let dithered = target + noise;
+        let sample = S::from_f32(if dithered >= 0.0 {
+            dithered + half
+        } else {
+            dithered - half
+        });
+        if self.mode == DitherMode::TpdfShaped {
+            // Clipping error isn't fed back, that would only prolong the clipping
+            self.error = (sample.to_f32() - target).clamp(-2.0 * S::LSB, 2.0 * S::LSB);
+        }
+        sample
+    }
+
+    /// Uniform in the range [-0.5, 0.5)
+    fn uniform(&mut self) -> f32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x >> 8) as f32 * (1.0 / 16777216.0) - 0.5
+    }
+}
+
+/// Convert words holding `S` samples to f32, stops at the end of the shorter slice
+pub fn words_to_f32<S: Sample>(input: &[u32], output: &mut [f32]) {
+    for (word, dest) in input.iter().zip(output.iter_mut()) {
+        *dest = S::from_word(*word).to_f32();
+    }
+}
+
+/// Convert f32 to words holding `S` samples, stops at the end of the shorter slice
+pub fn f32_to_words<S: Sample>(input: &[f32], output: &mut [u32]) {
+    for (x, dest) in input.iter().zip(output.iter_mut()) {
+        *dest = S::from_f32(*x).to_word();
+    }
+}
+
+pub fn s16_to_f32(input: &[u32], output: &mut [f32]) {
+    words_to_f32::<S16>(input, output);
+}
+
+pub fn f32_to_s16(input: &[f32], output: &mut [u32]) {
+    f32_to_words::<S16>(input, output);
+}
+
+pub fn s24_to_f32(input: &[u32], output: &mut [f32]) {
+    words_to_f32::<S24>(input, output);
+}
+
+pub fn f32_to_s24(input: &[f32], output: &mut [u32]) {
+    f32_to_words::<S24>(input, output);
+}
+
+pub fn s32_to_f32(input: &[u32], output: &mut [f32]) {
+    words_to_f32::<S32>(input, output);
+}
+
+pub fn f32_to_s32(input: &[f32], output: &mut [u32]) {
+    f32_to_words::<S32>(input, output);
+}
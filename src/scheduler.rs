@@ -0,0 +1,120 @@
+//! Run several tasks at their own rates from one timer interrupt
+//!
+//! Each task's period is divided down from the scheduler tick, so a 1 ms timer can scan
+//! controls every millisecond, refresh LEDs every 10 ms and redraw a display every 33 ms.
+//! Tasks are plain functions taking a shared context, usually a struct of the board's
+//! peripherals.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// Every task slot is in use
+    Full,
+    /// The period is zero or not a multiple of the tick
+    InvalidPeriod,
+    /// No task has this name
+    NotFound,
+}
+
+struct Task<C> {
+    name: &'static str,
+    /// Ticks between runs
+    divider: u32,
+    /// Ticks since the last run
+    count: u32,
+    enabled: bool,
+    callback: fn(&mut C),
+}
+
+pub struct Scheduler<C, const N: usize> {
+    tick_ms: u32,
+    tasks: [Option<Task<C>>; N],
+}
+
+impl<C, const N: usize> Scheduler<C, N> {
+    /// `tick_ms` is the period of the timer calling `tick`
+    pub fn new(tick_ms: u32) -> Self {
+        Self {
+            tick_ms,
+            tasks: core::array::from_fn(|_| None),
+        }
+    }
+
+    /// Run `callback` every `period_ms`, starting `period_ms` after it is added
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        period_ms: u32,
+        callback: fn(&mut C),
+    ) -> Result<(), Error> {
+        let divider = divider(self.tick_ms, period_ms).ok_or(Error::InvalidPeriod)?;
+        let slot = self
+            .tasks
+            .iter_mut()
+            .find(|task| task.is_none())
+            .ok_or(Error::Full)?;
+        *slot = Some(Task {
+            name,
+            divider,
+            count: 0,
+            enabled: true,
+            callback,
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let slot = self
+            .tasks
+            .iter_mut()
+            .find(|task| matches!(task, Some(task) if task.name == name))
+            .ok_or(Error::NotFound)?;
+        *slot = None;
+        Ok(())
+    }
+
+    /// Pause or resume a task, resuming restarts its period
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), Error> {
+        let task = self.task_mut(name)?;
+        task.enabled = enabled;
+        task.count = 0;
+        Ok(())
+    }
+
+    pub fn set_period(&mut self, name: &str, period_ms: u32) -> Result<(), Error> {
+        let divider = divider(self.tick_ms, period_ms).ok_or(Error::InvalidPeriod)?;
+        let task = self.task_mut(name)?;
+        task.divider = divider;
+        task.count = 0;
+        Ok(())
+    }
+
+    /// Call from the timer interrupt, runs every task that is due in the order they were added
+    pub fn tick(&mut self, context: &mut C) {
+        for task in self.tasks.iter_mut().flatten() {
+            if !task.enabled {
+                continue;
+            }
+            task.count += 1;
+            if task.count >= task.divider {
+                task.count = 0;
+                (task.callback)(context);
+            }
+        }
+    }
+
+    fn task_mut(&mut self, name: &str) -> Result<&mut Task<C>, Error> {
+        self.tasks
+            .iter_mut()
+            .flatten()
+            .find(|task| task.name == name)
+            .ok_or(Error::NotFound)
+    }
+}
+
+/// Ticks per period, `None` unless the period is a non zero multiple of the tick
+fn divider(tick_ms: u32, period_ms: u32) -> Option<u32> {
+    if tick_ms == 0 || period_ms == 0 || period_ms % tick_ms != 0 {
+        return None;
+    }
+    Some(period_ms / tick_ms)
+}
@@ -0,0 +1,249 @@
+//! Offline audio simulator for the host
+//!
+//! Runs an [`audio::Callback`] over a WAV file in blocks, as the audio interrupt would,
+//! and writes its output to a 24 bit WAV file. Input and output go through the same S24
+//! conversion as the codec so results match the hardware bit for bit, as long as the
+//! hardware `Output` keeps the default `DitherMode::None`. The simulator doesn't dither.
+//!
+//! Build for the host with `--no-default-features --features sim`.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::audio::{Callback, Frame, FRAMES_PER_CALLBACK};
+use crate::sample::{Sample, S24};
+use crate::wav;
+use crate::AUDIO_SAMPLE_RATE;
+
+/// Samples read from a WAV file at a time, the size in the header isn't trusted for allocation
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Not a WAV file this simulator can read
+    Format(&'static str),
+    /// The file's sample rate differs from the simulator's
+    SampleRate {
+        expected: u32,
+        found: u32,
+    },
+    /// The file's channel count differs from the callback's
+    Channels {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<wav::Error<io::Error>> for Error {
+    fn from(error: wav::Error<io::Error>) -> Self {
+        match error {
+            wav::Error::Io(error) => Error::Io(error),
+            wav::Error::Format(reason) => Error::Format(reason),
+            wav::Error::UnexpectedEnd => Error::Format("truncated file"),
+            wav::Error::Channels { expected, found } => Error::Channels { expected, found },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Format(reason) => write!(f, "unsupported WAV file: {}", reason),
+            Error::SampleRate { expected, found } => {
+                write!(f, "sample rate is {} Hz, expected {} Hz", found, expected)
+            }
+            Error::Channels { expected, found } => {
+                write!(f, "{} channels, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decoded WAV file, samples are interleaved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub samples: Vec<f32>,
+}
+
+impl Wav {
+    /// Read 16, 24 or 32 bit PCM or 32 bit float
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_reader(wav::Io(BufReader::new(File::open(path)?)))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_reader(wav::Io(bytes))
+    }
+
+    /// Write as 24 bit PCM
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let wav::Io(mut file) = self.write_to(wav::Io(BufWriter::new(File::create(path)?)))?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let wav::Io(cursor) = self
+            .write_to(wav::Io(io::Cursor::new(Vec::new())))
+            .expect("writing to memory can't fail");
+        cursor.into_inner()
+    }
+
+    fn from_reader<R: io::Read>(source: wav::Io<R>) -> Result<Self, Error> {
+        let mut reader = wav::Reader::new(source)?;
+        let format = reader.format();
+        let mut samples = Vec::new();
+        let mut chunk = [0.0; READ_CHUNK];
+        loop {
+            let count = reader.read_samples(&mut chunk)?;
+            if count == 0 {
+                break;
+            }
+            samples.extend_from_slice(&chunk[..count]);
+        }
+        Ok(Self {
+            sample_rate: format.sample_rate,
+            channels: format.channels as usize,
+            samples,
+        })
+    }
+
+    fn write_to<W: io::Write + io::Seek>(&self, sink: wav::Io<W>) -> Result<wav::Io<W>, Error> {
+        let format = wav::Format {
+            encoding: wav::Encoding::Pcm24,
+            channels: self.channels as u16,
+            sample_rate: self.sample_rate,
+        };
+        let mut writer = wav::Writer::new(sink, format)?;
+        writer.write_samples(&self.samples)?;
+        Ok(writer.finalize()?)
+    }
+}
+
+pub struct Simulator {
+    sample_rate: u32,
+    block_size: usize,
+}
+
+impl Simulator {
+    pub fn new(sample_rate: u32, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must not be zero");
+        Self {
+            sample_rate,
+            block_size,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Run `callback` over `input` one block at a time, returning one output frame per input frame
+    ///
+    /// The last block is padded with silence, as the hardware always delivers whole blocks.
+    pub fn process<C, const CHANNELS: usize>(
+        &self,
+        callback: &mut C,
+        input: &[Frame<CHANNELS>],
+    ) -> Vec<Frame<CHANNELS>>
+    where
+        C: Callback<CHANNELS>,
+    {
+        let mut output = Vec::with_capacity(input.len());
+        let mut in_block = vec![[0.0; CHANNELS]; self.block_size];
+        let mut out_block = vec![[0.0; CHANNELS]; self.block_size];
+        for frames in input.chunks(self.block_size) {
+            for (dest, frame) in in_block
+                .iter_mut()
+                .zip(frames.iter().chain(core::iter::repeat(&[0.0; CHANNELS])))
+            {
+                *dest = quantize(*frame);
+            }
+            for frame in out_block.iter_mut() {
+                *frame = [0.0; CHANNELS];
+            }
+            callback.process(&in_block, &mut out_block);
+            output.extend(
+                out_block
+                    .iter()
+                    .take(frames.len())
+                    .map(|frame| quantize(*frame)),
+            );
+        }
+        output
+    }
+
+    /// Run `callback` over the WAV file at `input` and write its output to `output`
+    pub fn process_file<C, const CHANNELS: usize>(
+        &self,
+        callback: &mut C,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> Result<(), Error>
+    where
+        C: Callback<CHANNELS>,
+    {
+        let wav = Wav::read(input)?;
+        if wav.sample_rate != self.sample_rate {
+            return Err(Error::SampleRate {
+                expected: self.sample_rate,
+                found: wav.sample_rate,
+            });
+        }
+        if wav.channels != CHANNELS {
+            return Err(Error::Channels {
+                expected: CHANNELS,
+                found: wav.channels,
+            });
+        }
+        let frames: Vec<Frame<CHANNELS>> = wav
+            .samples
+            .chunks_exact(CHANNELS)
+            .map(|samples| {
+                let mut frame = [0.0; CHANNELS];
+                frame.copy_from_slice(samples);
+                frame
+            })
+            .collect();
+        let processed = self.process(callback, &frames);
+        Wav {
+            sample_rate: self.sample_rate,
+            channels: CHANNELS,
+            samples: processed.iter().flatten().copied().collect(),
+        }
+        .write(output)
+    }
+}
+
+impl Default for Simulator {
+    /// The sample rate the hardware runs at and the frames `Audio::process` passes per callback
+    fn default() -> Self {
+        Self::new(AUDIO_SAMPLE_RATE, FRAMES_PER_CALLBACK)
+    }
+}
+
+/// Round trip through S24 like samples to and from the codec
+fn quantize<const CHANNELS: usize>(frame: Frame<CHANNELS>) -> Frame<CHANNELS> {
+    let mut out = [0.0; CHANNELS];
+    for (dest, sample) in out.iter_mut().zip(frame.iter()) {
+        *dest = S24::from_f32(*sample).to_f32();
+    }
+    out
+}
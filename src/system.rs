@@ -1,21 +1,30 @@
 #![allow(dead_code)]
 // #![allow(unused_variables)]
 
+use core::cell::Cell;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use cortex_m::interrupt::{self, Mutex};
 use cortex_m::peripheral::DWT;
 use log::info;
 
 use stm32h7xx_hal::adc;
-use stm32h7xx_hal::delay::Delay;
+use stm32h7xx_hal::gpio;
+use stm32h7xx_hal::gpio::{Alternate, AF10, AF6, AF8};
+use stm32h7xx_hal::hal::blocking::delay::{DelayMs, DelayUs};
 use stm32h7xx_hal::prelude::*;
 use stm32h7xx_hal::rcc;
 use stm32h7xx_hal::sai::*;
 use stm32h7xx_hal::stm32;
-use stm32h7xx_hal::stm32::rcc::d2ccip1r::SAI1SEL_A;
-use stm32h7xx_hal::stm32::TIM2;
+use stm32h7xx_hal::stm32::rcc::d2ccip1r::{SAI1SEL_A, SAI23SEL_A};
+use stm32h7xx_hal::stm32::{TIM2, TIM3, TIM4, TIM5};
 use stm32h7xx_hal::timer::Event;
 use stm32h7xx_hal::timer::Timer;
 
 use crate::audio;
+use crate::codec::Codec;
 use crate::*;
 
 const HSE_CLOCK_MHZ: MegaHertz = MegaHertz(16);
@@ -39,12 +48,7 @@ const PLL3_P_HZ: Hertz = Hertz(AUDIO_SAMPLE_HZ.0 * 257);
 const PLL3_Q_HZ: Hertz = Hertz(PLL3_P_HZ.0 / 4);
 const PLL3_R_HZ: Hertz = Hertz(PLL3_P_HZ.0 / 16);
 
-// Process samples at 1000 Hz
-// With a circular buffer(*2) in stereo (*2)
-pub const BLOCK_SIZE_MAX: usize = 48;
-pub const BUFFER_SIZE: usize = BLOCK_SIZE_MAX * 2 * 2;
-
-pub type IoBuffer = [u32; BUFFER_SIZE];
+pub use crate::audio::{IoBuffer, BLOCK_SIZE_MAX, BUFFER_SIZE};
 
 const SLOTS: u8 = 2;
 const FIRST_BIT_OFFSET: u8 = 0;
@@ -62,18 +66,390 @@ static mut buf_rx: IoBuffer = [0; BUFFER_SIZE];
 #[no_mangle]
 static mut sdram_buf: [f32; 48] = [0.0; 48];
 
-pub struct System {
+/// Core clock in cycles per microsecond, set from the frozen clocks in `System::init`
+static CYCLES_PER_MICRO: AtomicU32 = AtomicU32::new(CLOCK_RATE_HZ.0 / 1_000_000);
+
+fn cycles_per_micro() -> u64 {
+    CYCLES_PER_MICRO.load(Ordering::Relaxed) as u64
+}
+
+/// Last cycle count read and the number of times the counter has wrapped
+static CLOCK: Mutex<Cell<(u32, u32)>> = Mutex::new(Cell::new((0, 0)));
+
+/// Time since the cycle counter was enabled in `System::init`
+///
+/// The 32 bit cycle counter wraps about every 9 seconds, which is extended to 64 bits here.
+/// This only works if `now` is called at least once per wrap, calling it from a
+/// regularly running timer interrupt is enough.
+pub fn now() -> Instant {
+    // Read inside the critical section so an interrupt can't store a later count first
+    let (cycles, wraps) = interrupt::free(|cs| {
+        let cycles = DWT::get_cycle_count();
+        let clock = CLOCK.borrow(cs);
+        let (last, mut wraps) = clock.get();
+        if cycles < last {
+            wraps += 1;
+        }
+        clock.set((cycles, wraps));
+        (cycles, wraps)
+    });
+    Instant {
+        cycles: ((wraps as u64) << 32) | cycles as u64,
+    }
+}
+
+/// Point in time from the DWT cycle counter, see [`now`]
+///
+/// The counter wraps every 2^32 core cycles, about 8.9 seconds at 480MHz. Wraps are only
+/// counted when `now` runs, so it has to be called at least that often, for example from a
+/// timer interrupt. Otherwise instants, `Timeout`s included, come out a whole wrap early and
+/// timeouts expire late.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant {
+    cycles: u64,
+}
+
+impl Instant {
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.cycles / cycles_per_micro()
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.as_micros() / 1_000
+    }
+
+    /// Time since `earlier`, zero if `earlier` is later than `self`
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        let cycles = self.cycles.saturating_sub(earlier.cycles);
+        // Divide first, the product could overflow after a long uptime
+        let per_micro = cycles_per_micro();
+        Duration::from_micros(cycles / per_micro)
+            + Duration::from_nanos(cycles % per_micro * 1_000 / per_micro)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        let per_micro = cycles_per_micro();
+        let cycles = duration.as_secs() * per_micro * 1_000_000
+            + duration.subsec_nanos() as u64 * per_micro / 1_000;
+        Instant {
+            cycles: self.cycles + cycles,
+        }
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Deadline to poll instead of blocking, e.g. from `idle` or a timer interrupt
+///
+/// Relies on `now` running at least once per cycle counter wrap, see [`Instant`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timeout {
+    deadline: Instant,
+}
+
+impl Timeout {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            deadline: now() + duration,
+        }
+    }
+
+    pub fn from_millis(ms: u64) -> Self {
+        Self::new(Duration::from_millis(ms))
+    }
+
+    pub fn from_micros(us: u64) -> Self {
+        Self::new(Duration::from_micros(us))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now() >= self.deadline
+    }
+
+    /// Time left until the deadline, zero once expired
+    pub fn remaining(&self) -> Duration {
+        self.deadline.duration_since(now())
+    }
+
+    /// Busy wait until the deadline, unlike `delay_ms` this isn't stretched by interrupts
+    pub fn wait(&self) {
+        while !self.is_expired() {}
+    }
+}
+
+/// Blocking delay on the DWT cycle counter, for drivers taking embedded-hal delays
+///
+/// Unlike `delay_ms` this follows the configured core clock and isn't stretched by interrupts.
+#[derive(Debug, Copy, Clone)]
+pub struct Delay {
+    cycles_per_micro: u32,
+}
+
+impl Delay {
+    pub fn new(clocks: &rcc::CoreClocks) -> Self {
+        Self {
+            cycles_per_micro: clocks.c_ck().0 / 1_000_000,
+        }
+    }
+
+    fn wait_cycles(&self, mut cycles: u64) {
+        // Wait in steps short enough that the 32 bit counter can't wrap past the start
+        while cycles > 0 {
+            let step = cycles.min(u32::MAX as u64 / 2) as u32;
+            let start = DWT::get_cycle_count();
+            while DWT::get_cycle_count().wrapping_sub(start) < step {}
+            cycles -= step as u64;
+        }
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        self.wait_cycles(us as u64 * self.cycles_per_micro as u64);
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.wait_cycles(ms as u64 * 1_000 * self.cycles_per_micro as u64);
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+type Sai1Pins = (
+    gpio::gpioe::PE2<Alternate<AF6>>,
+    gpio::gpioe::PE5<Alternate<AF6>>,
+    gpio::gpioe::PE4<Alternate<AF6>>,
+    gpio::gpioe::PE6<Alternate<AF6>>,
+    Option<gpio::gpioe::PE3<Alternate<AF6>>>,
+);
+
+type Sai2Pins = (
+    gpio::gpioa::PA1<Alternate<AF10>>,
+    gpio::gpioa::PA2<Alternate<AF8>>,
+    gpio::gpiog::PG9<Alternate<AF10>>,
+    gpio::gpioa::PA0<Alternate<AF10>>,
+    Option<gpio::gpiod::PD11<Alternate<AF10>>>,
+);
+
+pub struct System<const CHANNELS: usize = { audio::STEREO }> {
     pub gpio: crate::gpio::GPIO,
-    pub audio: audio::Audio,
-    pub exit: stm32::EXTI,
+    pub audio: audio::Audio<CHANNELS>,
+    pub exti: stm32::EXTI,
     pub syscfg: stm32::SYSCFG,
     pub adc1: adc::Adc<stm32::ADC1, adc::Disabled>,
     pub adc2: adc::Adc<stm32::ADC2, adc::Disabled>,
     pub timer2: Timer<TIM2>,
+    /// 1 ms, interrupts enabled, see `scheduler::Scheduler` for running several rates from one timer
+    pub timer3: Timer<TIM3>,
+    /// 1 ms, interrupts enabled
+    pub timer4: Timer<TIM4>,
+    /// 1 ms, interrupts enabled
+    pub timer5: Timer<TIM5>,
+    pub clocks: rcc::CoreClocks,
+    pub delay: Delay,
+    /// Not configured, used by board support for displays
+    pub spi1: stm32::SPI1,
+    pub spi1_rec: rcc::rec::Spi1,
+    /// Not configured, used by board support for LED drivers
+    pub i2c1: stm32::I2C1,
+    pub i2c1_rec: rcc::rec::I2c1,
+    /// Not configured, see `cv::CvOut`
+    pub dac: stm32::DAC,
+    pub dac_rec: rcc::rec::Dac12,
+    /// Not configured, used by `cv::CvOut::enable_streams`
+    pub dma1: stm32::DMA1,
+    pub dma1_rec: rcc::rec::Dma1,
+    pub dmamux1: stm32::DMAMUX1,
+    /// Not configured, triggers the DAC in `cv::CvOut::enable_streams`
+    pub tim6: stm32::TIM6,
+    pub tim6_rec: rcc::rec::Tim6,
+    /// Not configured, used by board support for MIDI
+    pub usart1: stm32::USART1,
+    pub usart1_rec: rcc::rec::Usart1,
+}
+
+/// The `System` peripherals a board's `init` doesn't use, returned alongside the board
+///
+/// Peripherals the board takes for itself are `None`.
+pub struct Resources {
+    pub exti: stm32::EXTI,
+    pub syscfg: stm32::SYSCFG,
+    pub adc2: adc::Adc<stm32::ADC2, adc::Disabled>,
+    pub timer3: Timer<TIM3>,
+    pub timer4: Timer<TIM4>,
+    pub timer5: Timer<TIM5>,
+    pub clocks: rcc::CoreClocks,
+    pub delay: Delay,
+    pub spi1: Option<(stm32::SPI1, rcc::rec::Spi1)>,
+    pub i2c1: Option<(stm32::I2C1, rcc::rec::I2c1)>,
+    pub dac: Option<(stm32::DAC, rcc::rec::Dac12)>,
+    pub dma1: stm32::DMA1,
+    pub dma1_rec: rcc::rec::Dma1,
+    pub dmamux1: stm32::DMAMUX1,
+    pub tim6: stm32::TIM6,
+    pub tim6_rec: rcc::rec::Tim6,
+    pub usart1: Option<(stm32::USART1, rcc::rec::Usart1)>,
 }
 
 impl System {
-    pub fn init(mut core: cortex_m::Peripherals, device: stm32::Peripherals) -> System {
+    /// Stereo audio through the Seed's codec on SAI1
+    pub fn init(core: cortex_m::Peripherals, device: stm32::Peripherals) -> System {
+        let (parts, sai) = Parts::init(core, device);
+
+        info!("Setup up SAI...");
+        let dev_audio = init_sai1(sai.sai1, sai.sai1_pins, sai.sai1_rec, &sai.clocks);
+        let audio;
+        unsafe {
+            audio = audio::Audio::new(dev_audio, &mut buf_rx, &mut buf_tx);
+        }
+
+        parts.finish(audio)
+    }
+}
+
+impl System<{ audio::QUAD }> {
+    /// Quad audio through the Seed's codec on SAI1 and a second codec on SAI2
+    ///
+    /// SAI2 uses Daisy pins 24 to 28, which are taken from `gpio`.
+    pub fn init_quad(core: cortex_m::Peripherals, device: stm32::Peripherals) -> Self {
+        let (mut parts, sai) = Parts::init(core, device);
+
+        info!("Setup up SAI...");
+        let gpio = &mut parts.gpio;
+        let sai2_pins = (
+            gpio.daisy24.take().unwrap().into_alternate_af10(), // MCLK_B
+            gpio.daisy28.take().unwrap().into_alternate_af8(),  // SCK_B
+            gpio.daisy27.take().unwrap().into_alternate_af10(), // FS_B
+            gpio.daisy25.take().unwrap().into_alternate_af10(), // SD_B
+            Some(gpio.daisy26.take().unwrap().into_alternate_af10()), // SD_A
+        );
+        let dev_audio = init_sai1(sai.sai1, sai.sai1_pins, sai.sai1_rec, &sai.clocks);
+        let dev_audio2 = init_sai2(sai.sai2, sai2_pins, sai.sai2_rec, &sai.clocks);
+        let audio;
+        unsafe {
+            audio = audio::Audio::new_quad(dev_audio, dev_audio2, &mut buf_rx, &mut buf_tx);
+        }
+
+        parts.finish(audio)
+    }
+}
+
+impl<const CHANNELS: usize> System<CHANNELS> {
+    /// Split off the GPIO, audio, ADC1 and TIM2 that every board sets up from the rest
+    pub fn split(
+        self,
+    ) -> (
+        crate::gpio::GPIO,
+        audio::Audio<CHANNELS>,
+        adc::Adc<stm32::ADC1, adc::Disabled>,
+        Timer<TIM2>,
+        Resources,
+    ) {
+        let resources = Resources {
+            exti: self.exti,
+            syscfg: self.syscfg,
+            adc2: self.adc2,
+            timer3: self.timer3,
+            timer4: self.timer4,
+            timer5: self.timer5,
+            clocks: self.clocks,
+            delay: self.delay,
+            spi1: Some((self.spi1, self.spi1_rec)),
+            i2c1: Some((self.i2c1, self.i2c1_rec)),
+            dac: Some((self.dac, self.dac_rec)),
+            dma1: self.dma1,
+            dma1_rec: self.dma1_rec,
+            dmamux1: self.dmamux1,
+            tim6: self.tim6,
+            tim6_rec: self.tim6_rec,
+            usart1: Some((self.usart1, self.usart1_rec)),
+        };
+        (self.gpio, self.audio, self.adc1, self.timer2, resources)
+    }
+}
+
+/// Everything `System` sets up apart from the audio interfaces
+struct Parts {
+    gpio: crate::gpio::GPIO,
+    exti: stm32::EXTI,
+    syscfg: stm32::SYSCFG,
+    adc1: adc::Adc<stm32::ADC1, adc::Disabled>,
+    adc2: adc::Adc<stm32::ADC2, adc::Disabled>,
+    timer2: Timer<TIM2>,
+    timer3: Timer<TIM3>,
+    timer4: Timer<TIM4>,
+    timer5: Timer<TIM5>,
+    clocks: rcc::CoreClocks,
+    delay: Delay,
+    spi1: stm32::SPI1,
+    spi1_rec: rcc::rec::Spi1,
+    i2c1: stm32::I2C1,
+    i2c1_rec: rcc::rec::I2c1,
+    dac: stm32::DAC,
+    dac_rec: rcc::rec::Dac12,
+    dma1: stm32::DMA1,
+    dma1_rec: rcc::rec::Dma1,
+    dmamux1: stm32::DMAMUX1,
+    tim6: stm32::TIM6,
+    tim6_rec: rcc::rec::Tim6,
+    usart1: stm32::USART1,
+    usart1_rec: rcc::rec::Usart1,
+}
+
+/// Peripherals needed to set up the audio interfaces
+struct SaiParts {
+    clocks: rcc::CoreClocks,
+    sai1: stm32::SAI1,
+    sai1_pins: Sai1Pins,
+    sai1_rec: rcc::rec::Sai1,
+    sai2: stm32::SAI2,
+    sai2_rec: rcc::rec::Sai2,
+}
+
+impl Parts {
+    fn init(mut core: cortex_m::Peripherals, device: stm32::Peripherals) -> (Parts, SaiParts) {
         // let mut core = device::CorePeripherals::take().unwrap();
         info!("Starting system init");
         // Power
@@ -105,7 +481,13 @@ impl System {
 
         // log_clocks(&ccdr);
 
-        let mut delay = Delay::new(core.SYST, ccdr.clocks);
+        // The cycle counter is used for `now` and `Delay`
+        core.DCB.enable_trace();
+        DWT::unlock();
+        core.DWT.enable_cycle_counter();
+        CYCLES_PER_MICRO.store(ccdr.clocks.c_ck().0 / 1_000_000, Ordering::Relaxed);
+
+        let mut delay = Delay::new(&ccdr.clocks);
         // Setup ADCs
         let (adc1, adc2) = adc::adc12(
             device.ADC1,
@@ -126,21 +508,25 @@ impl System {
         // let mpu = unsafe { cortex_mpu::Mpu::new(core.MPU) };
 
         // Timers
-        // TODO
-        // ?
-        core.DCB.enable_trace();
-        DWT::unlock();
-        core.DWT.enable_cycle_counter();
-
         let mut timer2 = device
             .TIM2
             .timer(100.ms(), ccdr.peripheral.TIM2, &mut ccdr.clocks);
         timer2.listen(Event::TimeOut);
 
-        // let mut timer3 = device
-        //     .TIM3
-        //     .timer(1.ms(), ccdr.peripheral.TIM3, &mut ccdr.clocks);
-        // timer3.listen(Event::TimeOut);
+        let mut timer3 = device
+            .TIM3
+            .timer(1.ms(), ccdr.peripheral.TIM3, &mut ccdr.clocks);
+        timer3.listen(Event::TimeOut);
+
+        let mut timer4 = device
+            .TIM4
+            .timer(1.ms(), ccdr.peripheral.TIM4, &mut ccdr.clocks);
+        timer4.listen(Event::TimeOut);
+
+        let mut timer5 = device
+            .TIM5
+            .timer(1.ms(), ccdr.peripheral.TIM5, &mut ccdr.clocks);
+        timer5.listen(Event::TimeOut);
 
         // info!("Setting up GPIOs...");
         let gpioa = device.GPIOA.split(ccdr.peripheral.GPIOA);
@@ -151,7 +537,7 @@ impl System {
         let _gpiof = device.GPIOF.split(ccdr.peripheral.GPIOF);
         let gpiog = device.GPIOG.split(ccdr.peripheral.GPIOG);
 
-        let pins_a = (
+        let sai1_pins = (
             gpioe.pe2.into_alternate_af6(),       // MCLK_A
             gpioe.pe5.into_alternate_af6(),       // SCK_A
             gpioe.pe4.into_alternate_af6(),       // FS_A
@@ -175,27 +561,10 @@ impl System {
             pin_group[DSY_QSPI_PIN_NCS] =
             dsy_pin(DSY_GPIOG, 6);
         */
-        info!("Setup up SAI...");
 
+        // Both SAIs run from PLL3 so their frame clocks stay locked together
         let sai1_rec = ccdr.peripheral.SAI1.kernel_clk_mux(SAI1SEL_A::PLL3_P);
-        let master_config = I2SChanConfig::new(I2SDir::Tx).set_frame_sync_active_high(true);
-        let slave_config = I2SChanConfig::new(I2SDir::Rx)
-            .set_sync_type(I2SSync::Internal)
-            .set_frame_sync_active_high(true);
-
-        let dev_audio = device.SAI1.i2s_ch_a(
-            pins_a,
-            AUDIO_SAMPLE_HZ,
-            I2SDataSize::BITS_24,
-            sai1_rec,
-            &ccdr.clocks,
-            master_config,
-            Some(slave_config),
-        );
-        let audio;
-        unsafe {
-            audio = audio::Audio::new(dev_audio, &mut buf_rx, &mut buf_tx);
-        }
+        let sai2_rec = ccdr.peripheral.SAI2.kernel_clk_mux(SAI23SEL_A::PLL3_P);
 
         // ccdr.peripheral.DMA1.enable().reset();
         // ccdr.peripheral.DMA1.enable().reset();
@@ -208,8 +577,7 @@ impl System {
         // }
 
         // Setup GPIOs
-        let mut gpio = crate::gpio::GPIO::init(gpioa, gpiob, gpioc, gpiod, gpiog);
-        gpio.reset_codec();
+        let gpio = crate::gpio::GPIO::init(gpioa, gpiob, gpioc, gpiod, gpiog);
 
         // Setup cache
         core.SCB.invalidate_icache();
@@ -217,20 +585,125 @@ impl System {
         // core.SCB.clean_invalidate_dcache(&mut core.CPUID);
         // core.SCB.enable_dcache(&mut core.CPUID);
 
-        info!("System init done!");
-
-        System {
+        let parts = Parts {
             gpio,
-            audio,
-            exit: device.EXTI,
+            exti: device.EXTI,
             syscfg: device.SYSCFG,
             adc1,
             adc2,
             timer2,
+            timer3,
+            timer4,
+            timer5,
+            clocks: ccdr.clocks,
+            delay,
+            spi1: device.SPI1,
+            spi1_rec: ccdr.peripheral.SPI1,
+            i2c1: device.I2C1,
+            i2c1_rec: ccdr.peripheral.I2C1,
+            dac: device.DAC,
+            dac_rec: ccdr.peripheral.DAC12,
+            dma1: device.DMA1,
+            dma1_rec: ccdr.peripheral.DMA1,
+            dmamux1: device.DMAMUX1,
+            tim6: device.TIM6,
+            tim6_rec: ccdr.peripheral.TIM6,
+            usart1: device.USART1,
+            usart1_rec: ccdr.peripheral.USART1,
+        };
+        let sai = SaiParts {
+            clocks: ccdr.clocks,
+            sai1: device.SAI1,
+            sai1_pins,
+            sai1_rec,
+            sai2: device.SAI2,
+            sai2_rec,
+        };
+        (parts, sai)
+    }
+
+    /// The codec is reset once its SAI is running
+    fn finish<const CHANNELS: usize>(self, audio: audio::Audio<CHANNELS>) -> System<CHANNELS> {
+        let mut gpio = self.gpio;
+        gpio.codec.init(AUDIO_SAMPLE_HZ).unwrap();
+
+        info!("System init done!");
+
+        System {
+            gpio,
+            audio,
+            exti: self.exti,
+            syscfg: self.syscfg,
+            adc1: self.adc1,
+            adc2: self.adc2,
+            timer2: self.timer2,
+            timer3: self.timer3,
+            timer4: self.timer4,
+            timer5: self.timer5,
+            clocks: self.clocks,
+            delay: self.delay,
+            spi1: self.spi1,
+            spi1_rec: self.spi1_rec,
+            i2c1: self.i2c1,
+            i2c1_rec: self.i2c1_rec,
+            dac: self.dac,
+            dac_rec: self.dac_rec,
+            dma1: self.dma1,
+            dma1_rec: self.dma1_rec,
+            dmamux1: self.dmamux1,
+            tim6: self.tim6,
+            tim6_rec: self.tim6_rec,
+            usart1: self.usart1,
+            usart1_rec: self.usart1_rec,
         }
     }
 }
 
+fn init_sai1(
+    sai1: stm32::SAI1,
+    pins: Sai1Pins,
+    rec: rcc::rec::Sai1,
+    clocks: &rcc::CoreClocks,
+) -> Sai<stm32::SAI1, I2S> {
+    let master_config = I2SChanConfig::new(I2SDir::Tx).set_frame_sync_active_high(true);
+    let slave_config = I2SChanConfig::new(I2SDir::Rx)
+        .set_sync_type(I2SSync::Internal)
+        .set_frame_sync_active_high(true);
+
+    sai1.i2s_ch_a(
+        pins,
+        AUDIO_SAMPLE_HZ,
+        I2SDataSize::BITS_24,
+        rec,
+        clocks,
+        master_config,
+        Some(slave_config),
+    )
+}
+
+fn init_sai2(
+    sai2: stm32::SAI2,
+    pins: Sai2Pins,
+    rec: rcc::rec::Sai2,
+    clocks: &rcc::CoreClocks,
+) -> Sai<stm32::SAI2, I2S> {
+    // Block B receives from the second codec and block A transmits to it
+    let master_config = I2SChanConfig::new(I2SDir::Rx).set_frame_sync_active_high(true);
+    let slave_config = I2SChanConfig::new(I2SDir::Tx)
+        .set_sync_type(I2SSync::Internal)
+        .set_frame_sync_active_high(true);
+
+    sai2.i2s_ch_b(
+        pins,
+        AUDIO_SAMPLE_HZ,
+        I2SDataSize::BITS_24,
+        rec,
+        clocks,
+        master_config,
+        Some(slave_config),
+    )
+}
+
 fn log_clocks(ccdr: &stm32h7xx_hal::rcc::Ccdr) {
     info!("Core {}", ccdr.clocks.c_ck());
     info!("pclk1 {}", ccdr.clocks.pclk1());
@@ -0,0 +1,529 @@
+//! WAV file reader and writer without allocation
+//!
+//! Reads RIFF/WAVE files holding 16, 24 or 32 bit PCM or 32 bit float samples at any sample
+//! rate, and writes them back. Bytes come from a [`Read`] source and go to a [`Write`] sink,
+//! implemented for byte slices here and easy to implement for files on an SD card.
+//!
+//! Samples are converted through f32, which is exact for up to 24 bits.
+use core::convert::Infallible;
+use core::fmt;
+
+use crate::audio::Frame;
+use crate::sample::{Sample, S16, S24, S32};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Size of the header the writer emits, up to the first sample
+pub const HEADER_SIZE: usize = 44;
+const RIFF_SIZE_OFFSET: u32 = 4;
+const DATA_SIZE_OFFSET: u32 = 40;
+
+/// Bytes converted per call to the source or sink, a multiple of every sample size
+const CHUNK_SIZE: usize = 96;
+
+/// Source of bytes, like `std::io::Read`
+pub trait Read {
+    type Error;
+
+    /// Read up to `buffer.len()` bytes, returning how many were read, 0 at the end
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// Sink for bytes that can go back to patch the header, like `std::io::Write + Seek`
+pub trait Write {
+    type Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Move to `position` bytes from the start
+    fn seek(&mut self, position: u32) -> Result<(), Self::Error>;
+}
+
+impl Read for &[u8] {
+    type Error = Infallible;
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let count = buffer.len().min(self.len());
+        let (bytes, rest) = self.split_at(count);
+        buffer[..count].copy_from_slice(bytes);
+        *self = rest;
+        Ok(count)
+    }
+}
+
+/// The sink ran out of space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BufferFull;
+
+/// Writes into a byte slice, e.g. a recording buffer in SDRAM
+pub struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+    len: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            position: 0,
+            len: 0,
+        }
+    }
+
+    /// Bytes written so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl Write for SliceWriter<'_> {
+    type Error = BufferFull;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let end = self.position + bytes.len();
+        self.buffer
+            .get_mut(self.position..end)
+            .ok_or(BufferFull)?
+            .copy_from_slice(bytes);
+        self.position = end;
+        self.len = self.len.max(end);
+        Ok(())
+    }
+
+    fn seek(&mut self, position: u32) -> Result<(), Self::Error> {
+        if position as usize > self.buffer.len() {
+            return Err(BufferFull);
+        }
+        self.position = position as usize;
+        Ok(())
+    }
+}
+
+/// Adapter for `std::io` readers and writers
+#[cfg(feature = "std")]
+pub struct Io<T>(pub T);
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read> Read for Io<T> {
+    type Error = std::io::Error;
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buffer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Write + std::io::Seek> Write for Io<T> {
+    type Error = std::io::Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes)
+    }
+
+    fn seek(&mut self, position: u32) -> Result<(), Self::Error> {
+        self.0.seek(std::io::SeekFrom::Start(position as u64))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error<E> {
+    /// Error from the source or sink
+    Io(E),
+    /// Not a WAV file this module can read
+    Format(&'static str),
+    /// The source ended in the middle of the header or a sample
+    UnexpectedEnd,
+    /// Frames have a channel count the file can't be converted to or from, `expected` is the
+    /// frames' channel count and `found` the file's
+    Channels { expected: usize, found: usize },
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{:?}", error),
+            Error::Format(reason) => write!(f, "unsupported WAV file: {}", reason),
+            Error::UnexpectedEnd => write!(f, "unexpected end of WAV file"),
+            Error::Channels { expected, found } => {
+                write!(f, "{} channels, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug> std::error::Error for Error<E> {}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding {
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+}
+
+impl Encoding {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            Encoding::Pcm16 => 2,
+            Encoding::Pcm24 => 3,
+            Encoding::Pcm32 | Encoding::Float32 => 4,
+        }
+    }
+
+    fn tag(&self) -> u16 {
+        match self {
+            Encoding::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+            _ => WAVE_FORMAT_PCM,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> f32 {
+        match self {
+            Encoding::Pcm16 => S16(i16::from_le_bytes([bytes[0], bytes[1]])).to_f32(),
+            Encoding::Pcm24 => {
+                S24::from_word(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])).to_f32()
+            }
+            Encoding::Pcm32 => {
+                S32(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).to_f32()
+            }
+            Encoding::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    fn encode(&self, x: f32, bytes: &mut [u8]) {
+        match self {
+            Encoding::Pcm16 => bytes.copy_from_slice(&S16::from_f32(x).0.to_le_bytes()),
+            Encoding::Pcm24 => {
+                bytes.copy_from_slice(&S24::from_f32(x).to_word().to_le_bytes()[..3])
+            }
+            Encoding::Pcm32 => bytes.copy_from_slice(&S32::from_f32(x).0.to_le_bytes()),
+            Encoding::Float32 => bytes.copy_from_slice(&x.to_le_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl Format {
+    /// Bytes per frame
+    pub fn block_align(&self) -> usize {
+        self.encoding.bytes_per_sample() * self.channels as usize
+    }
+
+    /// Parse the body of a fmt chunk
+    fn parse(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() < 16 {
+            return Err("fmt chunk too short");
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let mut tag = u16_at(0);
+        let channels = u16_at(2);
+        let sample_rate = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let bits = u16_at(14);
+        if tag == WAVE_FORMAT_EXTENSIBLE && bytes.len() >= 26 {
+            // The sub format GUID starts with the format tag
+            tag = u16_at(24);
+        }
+        let encoding = match (tag, bits) {
+            (WAVE_FORMAT_PCM, 16) => Encoding::Pcm16,
+            (WAVE_FORMAT_PCM, 24) => Encoding::Pcm24,
+            (WAVE_FORMAT_PCM, 32) => Encoding::Pcm32,
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Encoding::Float32,
+            _ => return Err("only 16, 24 and 32 bit PCM or 32 bit float"),
+        };
+        if channels == 0 {
+            return Err("no channels");
+        }
+        Ok(Self {
+            encoding,
+            channels,
+            sample_rate,
+        })
+    }
+}
+
+/// Streams samples out of a WAV file
+pub struct Reader<R> {
+    source: R,
+    format: Format,
+    /// Bytes left in the data chunk
+    remaining: u32,
+}
+
+impl<R: Read> Reader<R> {
+    /// Parse the header, leaving `source` at the first sample
+    ///
+    /// Chunks other than fmt and data are skipped, the fmt chunk must come first.
+    pub fn new(mut source: R) -> Result<Self, Error<R::Error>> {
+        let mut header = [0; 12];
+        read_exact(&mut source, &mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(Error::Format("missing RIFF/WAVE header"));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            read_exact(&mut source, &mut chunk)?;
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            match &chunk[0..4] {
+                b"fmt " => {
+                    let size = padded(size)?;
+                    // Long enough for WAVE_FORMAT_EXTENSIBLE
+                    let mut body = [0; 40];
+                    let len = (size as usize).min(body.len());
+                    read_exact(&mut source, &mut body[..len])?;
+                    skip(&mut source, size - len as u32)?;
+                    format = Some(Format::parse(&body[..len]).map_err(Error::Format)?);
+                }
+                b"data" => {
+                    let format = format.ok_or(Error::Format("data chunk before fmt chunk"))?;
+                    return Ok(Self {
+                        source,
+                        format,
+                        remaining: size - size % format.block_align() as u32,
+                    });
+                }
+                _ => skip(&mut source, padded(size)?)?,
+            }
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Frames left to read
+    pub fn frames_remaining(&self) -> usize {
+        self.remaining as usize / self.format.block_align()
+    }
+
+    /// Read interleaved samples, returns how many were read
+    ///
+    /// Fewer than `samples.len()` are read only at the end of the data.
+    pub fn read_samples<S: Sample>(&mut self, samples: &mut [S]) -> Result<usize, Error<R::Error>> {
+        self.read(samples.len(), |i, x| samples[i] = S::from_f32(x))
+    }
+
+    /// Read whole frames, returns how many were read
+    ///
+    /// Mono files are copied to every channel, otherwise the file must have `CHANNELS` channels.
+    pub fn read_frames<const CHANNELS: usize>(
+        &mut self,
+        frames: &mut [Frame<CHANNELS>],
+    ) -> Result<usize, Error<R::Error>> {
+        match self.format.channels as usize {
+            1 => {
+                let mut count = 0;
+                let mut sample = [0.0];
+                for frame in frames.iter_mut() {
+                    if self.read_samples(&mut sample)? == 0 {
+                        break;
+                    }
+                    *frame = [sample[0]; CHANNELS];
+                    count += 1;
+                }
+                Ok(count)
+            }
+            channels if channels == CHANNELS => {
+                let count = self.read(frames.len() * CHANNELS, |i, x| {
+                    frames[i / CHANNELS][i % CHANNELS] = x
+                })?;
+                Ok(count / CHANNELS)
+            }
+            channels => Err(Error::Channels {
+                expected: CHANNELS,
+                found: channels,
+            }),
+        }
+    }
+
+    pub fn free(self) -> R {
+        self.source
+    }
+
+    /// Decode up to `count` samples, passing each to `store` with its index
+    fn read(
+        &mut self,
+        count: usize,
+        mut store: impl FnMut(usize, f32),
+    ) -> Result<usize, Error<R::Error>> {
+        let size = self.format.encoding.bytes_per_sample();
+        let mut buffer = [0; CHUNK_SIZE];
+        let mut read = 0;
+        while read < count && self.remaining as usize >= size {
+            let n = (count - read)
+                .min(CHUNK_SIZE / size)
+                .min(self.remaining as usize / size);
+            let bytes = &mut buffer[..n * size];
+            read_exact(&mut self.source, bytes)?;
+            self.remaining -= bytes.len() as u32;
+            for bytes in bytes.chunks_exact(size) {
+                store(read, self.format.encoding.decode(bytes));
+                read += 1;
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Writes a WAV file, sizes in the header are filled in by `finalize`
+pub struct Writer<W> {
+    sink: W,
+    format: Format,
+    /// Bytes written to the data chunk
+    data_size: u32,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the header, `sink` should be at the start of the file
+    pub fn new(mut sink: W, format: Format) -> Result<Self, Error<W::Error>> {
+        if format.channels == 0 {
+            return Err(Error::Format("no channels"));
+        }
+        let block_align = format.block_align() as u16;
+        let byte_rate = format
+            .sample_rate
+            .checked_mul(block_align as u32)
+            .ok_or(Error::Format("byte rate doesn't fit in 32 bits"))?;
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(b"RIFF");
+        header[8..12].copy_from_slice(b"WAVE");
+        header[12..16].copy_from_slice(b"fmt ");
+        header[16..20].copy_from_slice(&16u32.to_le_bytes());
+        header[20..22].copy_from_slice(&format.encoding.tag().to_le_bytes());
+        header[22..24].copy_from_slice(&format.channels.to_le_bytes());
+        header[24..28].copy_from_slice(&format.sample_rate.to_le_bytes());
+        header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+        header[32..34].copy_from_slice(&block_align.to_le_bytes());
+        header[34..36]
+            .copy_from_slice(&(format.encoding.bytes_per_sample() as u16 * 8).to_le_bytes());
+        header[36..40].copy_from_slice(b"data");
+        sink.write_all(&header).map_err(Error::Io)?;
+        Ok(Self {
+            sink,
+            format,
+            data_size: 0,
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Whole frames written so far
+    pub fn frames_written(&self) -> usize {
+        self.data_size as usize / self.format.block_align()
+    }
+
+    /// Write interleaved samples
+    ///
+    /// Fails without writing anything if the file would grow past the 4 GiB the RIFF sizes allow.
+    pub fn write_samples<S: Sample>(&mut self, samples: &[S]) -> Result<(), Error<W::Error>> {
+        self.write(samples.len(), samples.iter().map(|sample| sample.to_f32()))
+    }
+
+    /// Write whole frames, the file must have `CHANNELS` channels
+    pub fn write_frames<const CHANNELS: usize>(
+        &mut self,
+        frames: &[Frame<CHANNELS>],
+    ) -> Result<(), Error<W::Error>> {
+        if self.format.channels as usize != CHANNELS {
+            return Err(Error::Channels {
+                expected: CHANNELS,
+                found: self.format.channels as usize,
+            });
+        }
+        self.write(frames.len() * CHANNELS, frames.iter().flatten().copied())
+    }
+
+    /// Pad the data chunk and fill in the sizes, returns the sink positioned after the data
+    pub fn finalize(mut self) -> Result<W, Error<W::Error>> {
+        let mut end = HEADER_SIZE as u32 + self.data_size;
+        if self.data_size % 2 == 1 {
+            self.sink.write_all(&[0]).map_err(Error::Io)?;
+            end += 1;
+        }
+        self.patch(RIFF_SIZE_OFFSET, end - 8)?;
+        self.patch(DATA_SIZE_OFFSET, self.data_size)?;
+        self.sink.seek(end).map_err(Error::Io)?;
+        Ok(self.sink)
+    }
+
+    /// Encode `count` samples
+    fn write(
+        &mut self,
+        count: usize,
+        mut samples: impl Iterator<Item = f32>,
+    ) -> Result<(), Error<W::Error>> {
+        let size = self.format.encoding.bytes_per_sample();
+        // Leaves room for the header and the pad byte `finalize` may add
+        let limit = u32::MAX as u64 - HEADER_SIZE as u64 - 1;
+        if self.data_size as u64 + count as u64 * size as u64 > limit {
+            return Err(Error::Format("data doesn't fit in a WAV file"));
+        }
+        let mut buffer = [0; CHUNK_SIZE];
+        loop {
+            // Buffer first, so a sample isn't taken when the buffer is full
+            let mut len = 0;
+            for (bytes, sample) in buffer.chunks_exact_mut(size).zip(&mut samples) {
+                self.format.encoding.encode(sample, bytes);
+                len += size;
+            }
+            if len == 0 {
+                return Ok(());
+            }
+            self.sink.write_all(&buffer[..len]).map_err(Error::Io)?;
+            self.data_size += len as u32;
+        }
+    }
+
+    fn patch(&mut self, position: u32, value: u32) -> Result<(), Error<W::Error>> {
+        self.sink.seek(position).map_err(Error::Io)?;
+        self.sink.write_all(&value.to_le_bytes()).map_err(Error::Io)
+    }
+}
+
+fn read_exact<R: Read>(source: &mut R, mut buffer: &mut [u8]) -> Result<(), Error<R::Error>> {
+    while !buffer.is_empty() {
+        match source.read(buffer).map_err(Error::Io)? {
+            0 => return Err(Error::UnexpectedEnd),
+            n => buffer = &mut buffer[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Size of a chunk including the pad byte that keeps chunks at even offsets
+fn padded<E>(size: u32) -> Result<u32, Error<E>> {
+    size.checked_add(size % 2)
+        .ok_or(Error::Format("chunk size overflows"))
+}
+
+fn skip<R: Read>(source: &mut R, mut count: u32) -> Result<(), Error<R::Error>> {
+    let mut buffer = [0; CHUNK_SIZE];
+    while count > 0 {
+        let n = (count as usize).min(CHUNK_SIZE);
+        read_exact(source, &mut buffer[..n])?;
+        count -= n as u32;
+    }
+    Ok(())
+}
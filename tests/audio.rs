@@ -0,0 +1,99 @@
+use libdaisy_rust::audio::*;
+
+fn buffer(samples: &[f32]) -> &'static mut IoBuffer {
+    let buffer = Box::leak(Box::new([0; BUFFER_SIZE]));
+    for (word, sample) in buffer.iter_mut().zip(samples.iter()) {
+        *word = S24::from(*sample).into();
+    }
+    buffer
+}
+
+#[test]
+fn input_iterators() {
+    let input: Input = Input::new(buffer(&[0.5, -0.25]));
+
+    assert_eq!(input.frames().collect::<Vec<_>>(), [[0.5, -0.25]]);
+    assert_eq!(
+        input.get_stereo_iter().unwrap().collect::<Vec<_>>(),
+        [(0.5, -0.25)]
+    );
+    assert_eq!(input.left().collect::<Vec<_>>(), [0.5]);
+    assert_eq!(input.right().collect::<Vec<_>>(), [-0.25]);
+    assert_eq!(input.channel(2).count(), 0);
+}
+
+#[test]
+fn quad_input_iterators() {
+    let input: Input<QUAD> = Input::new(buffer(&[0.5, -0.25, 0.125, -1.0]));
+
+    let frame = input.frames().next().unwrap();
+    assert_eq!(frame[..3], [0.5, -0.25, 0.125]);
+    assert!(frame[3] < -0.99);
+    // Only the first two channels
+    assert_eq!(
+        input.get_stereo_iter().unwrap().collect::<Vec<_>>(),
+        [(0.5, -0.25)]
+    );
+    assert_eq!(input.channel(2).collect::<Vec<_>>(), [0.125]);
+}
+
+#[test]
+fn input_deinterleave() {
+    let input: Input = Input::new(buffer(&[0.5, -0.25]));
+    let mut buffers = [[0.0; 4]; STEREO];
+    assert_eq!(input.deinterleave(&mut buffers), 1);
+    assert_eq!(buffers, [[0.5, 0.0, 0.0, 0.0], [-0.25, 0.0, 0.0, 0.0]]);
+}
+
+#[test]
+fn output_channel_views() {
+    let mut output: Output<QUAD> = Output::new(buffer(&[1.0; 8]));
+
+    output.channel(2).write(&[0.5, 0.25]).unwrap();
+    output.left().push(-0.5).unwrap();
+    // Frames nothing was written to yet start out silent
+    assert_eq!(
+        output.frames().collect::<Vec<_>>(),
+        [[-0.5, 0.0, 0.5, 0.0], [0.0, 0.0, 0.25, 0.0]]
+    );
+    assert!(output.channel(QUAD).push(0.0).is_err());
+
+    // Whole frames continue after the frames the views wrote
+    output.push_mono(0.125).unwrap();
+    assert_eq!(output.frames().nth(2), Some([0.125; QUAD]));
+
+    let mut right = output.right();
+    for _ in 0..BLOCK_SIZE_MAX {
+        right.push(0.0).unwrap();
+    }
+    assert!(right.push(0.0).is_err());
+    assert!(output.push_mono(0.0).is_err());
+    assert_eq!(output.frames().count(), BLOCK_SIZE_MAX);
+}
+
+#[test]
+fn output_holds_one_block() {
+    let mut output: Output = Output::new(buffer(&[]));
+    for _ in 0..BLOCK_SIZE_MAX {
+        assert!(output.push((0.5, -0.5)).is_ok());
+    }
+    assert!(output.push_mono(0.0).is_err());
+
+    let mut output: Output = Output::new(buffer(&[]));
+    let buffers = [[0.0; BLOCK_SIZE_MAX]; STEREO];
+    assert!(output.interleave(&buffers, BLOCK_SIZE_MAX).is_ok());
+    assert!(output.interleave(&buffers, 1).is_err());
+}
+
+#[test]
+fn closures_are_callbacks() {
+    let mut gain = |input: &[Frame<STEREO>], output: &mut [Frame<STEREO>]| {
+        for (out, frame) in output.iter_mut().zip(input.iter()) {
+            *out = [frame[0] * 0.5, frame[1] * 0.5];
+        }
+    };
+    let input = [[0.5, -1.0]; 2];
+    let mut output = [[0.0; STEREO]; 2];
+    Callback::process(&mut gain, &input, &mut output);
+    assert_eq!(output, [[0.25, -0.5]; 2]);
+}
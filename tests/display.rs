@@ -0,0 +1,49 @@
+use embedded_hal_mock::eh0::digital::{Mock as PinMock, State, Transaction as PinTransaction};
+use embedded_hal_mock::eh0::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+
+use libdaisy_rust::display::*;
+
+#[test]
+fn pixels() {
+    let mut spi = SpiMock::new(&[]);
+    let mut pin = PinMock::new(&[]);
+    let mut display = Oled::new(spi.clone(), pin.clone(), pin.clone(), pin.clone());
+    display.set_pixel(3, 9, true);
+    assert!(display.get_pixel(3, 9));
+    assert!(!display.get_pixel(3, 8));
+    // Outside the display
+    display.set_pixel(WIDTH, 0, true);
+    assert!(!display.get_pixel(WIDTH, 0));
+
+    display.fill(true);
+    assert!(display.get_pixel(WIDTH - 1, HEIGHT - 1));
+    display.clear();
+    assert!(!display.get_pixel(3, 9));
+
+    spi.done();
+    pin.done();
+}
+
+#[test]
+fn flush_sends_frame_buffer() {
+    use State::*;
+    let mut frame = vec![0; WIDTH * HEIGHT / 8];
+    // Page 1, bit 1
+    frame[WIDTH + 3] = 0x02;
+    let mut spi = SpiMock::new(&[
+        SpiTransaction::write(vec![0x21, 0, 127, 0x22, 0, 7]),
+        SpiTransaction::write(frame),
+    ]);
+    let mut dc = PinMock::new(&[PinTransaction::set(Low), PinTransaction::set(High)]);
+    let mut cs = PinMock::new(&[Low, High, Low, High].map(PinTransaction::set));
+    let mut reset = PinMock::new(&[]);
+    let mut display = Oled::new(spi.clone(), dc.clone(), reset.clone(), cs.clone());
+
+    display.set_pixel(3, 9, true);
+    display.flush().unwrap();
+
+    spi.done();
+    dc.done();
+    cs.done();
+    reset.done();
+}
@@ -0,0 +1,535 @@
+use embedded_hal_mock::eh0::adc;
+use embedded_hal_mock::eh0::digital::{Mock as PinMock, State, Transaction as PinTransaction};
+
+use libdaisy_rust::hid::*;
+
+fn reads(levels: &[State]) -> Vec<PinTransaction> {
+    levels
+        .iter()
+        .map(|level| PinTransaction::get(*level))
+        .collect()
+}
+
+fn writes(levels: &[State]) -> Vec<PinTransaction> {
+    levels
+        .iter()
+        .map(|level| PinTransaction::set(*level))
+        .collect()
+}
+
+#[test]
+fn switch_debounces_press_and_release() {
+    use State::*;
+    let mut pin = PinMock::new(&reads(&[Low, Low, Low, Low, Low, High, High, High, High]));
+    let mut switch = Switch::new(pin.clone(), SwitchType::PullUp);
+
+    for _ in 0..3 {
+        switch.update();
+        assert!(!switch.is_rising());
+        assert!(!switch.is_high());
+    }
+    switch.update();
+    assert!(switch.is_rising());
+    assert!(switch.is_high());
+
+    switch.update();
+    assert!(!switch.is_rising());
+
+    for _ in 0..3 {
+        switch.update();
+        assert!(!switch.is_falling());
+    }
+    switch.update();
+    assert!(switch.is_falling());
+    assert!(switch.is_low());
+
+    pin.done();
+}
+
+#[test]
+fn switch_pull_down_is_pressed_high() {
+    let mut pin = PinMock::new(&reads(&[State::High; 4]));
+    let mut switch = Switch::new(pin.clone(), SwitchType::PullDown);
+    for _ in 0..4 {
+        switch.update();
+    }
+    assert!(switch.is_rising());
+    pin.done();
+}
+
+#[test]
+fn switch_held() {
+    use State::*;
+    let mut levels = vec![Low; 10];
+    levels.extend_from_slice(&[High; 4]);
+    let mut pin = PinMock::new(&reads(&levels));
+    let mut switch = Switch::new(pin.clone(), SwitchType::PullUp);
+    switch.set_held_thresh(Some(5));
+
+    for _ in 0..levels.len() {
+        switch.update();
+        assert_eq!(switch.is_held(), switch.is_falling());
+    }
+    assert!(switch.is_held());
+    pin.done();
+}
+
+#[test]
+fn switch_double_press() {
+    use State::*;
+    let press = [Low, Low, Low, Low, High, High, High, High];
+    let mut levels = press.to_vec();
+    levels.extend_from_slice(&press);
+    let mut pin = PinMock::new(&reads(&levels));
+    let mut switch = Switch::new(pin.clone(), SwitchType::PullUp);
+    switch.set_double_thresh(Some(10));
+
+    let mut doubles = 0;
+    for _ in 0..levels.len() {
+        switch.update();
+        doubles += switch.is_double() as u32;
+    }
+    assert_eq!(doubles, 1);
+    assert!(switch.is_double());
+    pin.done();
+}
+
+#[test]
+fn analog_control_averages_and_transforms() {
+    let mut control = AnalogControl::new((), 100.0);
+    for value in [10, 20, 30, 40] {
+        control.update(value);
+    }
+    assert!((control.get_value() - 0.25).abs() < 1e-6);
+
+    // Oldest reading is replaced
+    control.update(50);
+    assert!((control.get_value() - 0.35).abs() < 1e-6);
+
+    control.set_transform(|x| 1.0 - x);
+    assert!((control.get_value() - 0.65).abs() < 1e-6);
+}
+
+#[test]
+fn muxed_analog_inputs_cycle_channels() {
+    use State::*;
+    let mut adc = adc::Mock::new(&[
+        adc::Transaction::read(0, 100u32),
+        adc::Transaction::read(0, 200u32),
+    ]);
+    let mut select0 = PinMock::new(&writes(&[Low, High, Low]));
+    let mut select1 = PinMock::new(&writes(&[Low, Low, High]));
+    let mut select2 = PinMock::new(&writes(&[Low, Low, Low]));
+    let mut mux = MuxedAnalogInputs::new(
+        adc::MockChan0,
+        select0.clone(),
+        select1.clone(),
+        select2.clone(),
+        100.0,
+    );
+    mux.set_settle_ticks(1);
+
+    assert_eq!(mux.channel(), 0);
+    // Waits for the multiplexer to settle
+    mux.update(&mut adc);
+    assert_eq!(mux.channel(), 0);
+    mux.update(&mut adc);
+    assert_eq!(mux.channel(), 1);
+    mux.update(&mut adc);
+    mux.update(&mut adc);
+    assert_eq!(mux.channel(), 2);
+
+    assert!((mux.get_value(0) - 0.25).abs() < 1e-6);
+    assert!((mux.get_value(1) - 0.5).abs() < 1e-6);
+    assert_eq!(mux.get_value(2), 0.0);
+
+    adc.done();
+    select0.done();
+    select1.done();
+    select2.done();
+}
+
+#[test]
+fn muxed_analog_inputs_wrap_around() {
+    // Select lines for channels 0 to 7 then back to 0
+    let select = |bit: usize| {
+        let levels: Vec<State> = (0..=MUX_CHANNELS)
+            .map(|channel| {
+                if (channel % MUX_CHANNELS) & (1 << bit) != 0 {
+                    State::High
+                } else {
+                    State::Low
+                }
+            })
+            .collect();
+        PinMock::new(&writes(&levels))
+    };
+    let readings: Vec<_> = (0..MUX_CHANNELS as u32)
+        .map(|channel| adc::Transaction::read(0, channel * 10))
+        .collect();
+    let mut adc = adc::Mock::new(&readings);
+    let (mut select0, mut select1, mut select2) = (select(0), select(1), select(2));
+    let mut mux = MuxedAnalogInputs::new(
+        adc::MockChan0,
+        select0.clone(),
+        select1.clone(),
+        select2.clone(),
+        100.0,
+    );
+
+    for _ in 0..MUX_CHANNELS {
+        mux.update(&mut adc);
+    }
+    assert_eq!(mux.channel(), 0);
+    for channel in 0..MUX_CHANNELS {
+        let expected = channel as f32 * 10.0 / 100.0 / 4.0;
+        assert!((mux.get_value(channel) - expected).abs() < 1e-6);
+    }
+
+    adc.done();
+    select0.done();
+    select1.done();
+    select2.done();
+}
+
+#[test]
+fn muxed_analog_inputs_retry_failed_reads() {
+    use embedded_hal_mock::eh0::MockError;
+    use std::io::ErrorKind;
+    use State::*;
+    let mut adc = adc::Mock::new(&[
+        adc::Transaction::read(0, 0u32).with_error(MockError::Io(ErrorKind::TimedOut)),
+        adc::Transaction::read(0, 100u32),
+    ]);
+    let mut select0 = PinMock::new(&writes(&[Low, High]));
+    let mut select1 = PinMock::new(&writes(&[Low, Low]));
+    let mut select2 = PinMock::new(&writes(&[Low, Low]));
+    let mut mux = MuxedAnalogInputs::new(
+        adc::MockChan0,
+        select0.clone(),
+        select1.clone(),
+        select2.clone(),
+        100.0,
+    );
+
+    mux.update(&mut adc);
+    assert_eq!(mux.channel(), 0);
+    mux.update(&mut adc);
+    assert_eq!(mux.channel(), 1);
+    assert!((mux.get_value(0) - 0.25).abs() < 1e-6);
+
+    adc.done();
+    select0.done();
+    select1.done();
+    select2.done();
+}
+
+#[test]
+fn led_pwm() {
+    use State::*;
+    let mut pin = PinMock::new(&writes(&[High, High, High, Low]));
+    let mut led = Led::new(pin.clone(), false, 4);
+    led.set_brightness(1.0);
+    for _ in 0..4 {
+        led.update();
+    }
+    pin.done();
+}
+
+#[test]
+fn led_inverted_off_drives_high() {
+    let mut pin = PinMock::new(&writes(&[State::High; 4]));
+    let mut led = Led::new(pin.clone(), true, 4);
+    led.set_brightness(0.0);
+    for _ in 0..4 {
+        led.update();
+    }
+    pin.done();
+}
+
+#[test]
+fn encoder_direction() {
+    use State::*;
+    let mut a = PinMock::new(&reads(&[High, Low, Low, Low]));
+    let mut b = PinMock::new(&reads(&[Low, Low, Low, Low]));
+    let mut click = PinMock::new(&reads(&[High; 4]));
+    let mut encoder = Encoder::new(a.clone(), b.clone(), click.clone(), SwitchType::PullUp);
+
+    encoder.update();
+    assert_eq!(encoder.increment(), 0);
+    encoder.update();
+    assert_eq!(encoder.increment(), 1);
+    encoder.update();
+    assert_eq!(encoder.increment(), 0);
+    encoder.update();
+    assert_eq!(encoder.increment(), 0);
+
+    a.done();
+    b.done();
+    click.done();
+
+    let mut a = PinMock::new(&reads(&[Low, Low]));
+    let mut b = PinMock::new(&reads(&[High, Low]));
+    let mut click = PinMock::new(&reads(&[High; 2]));
+    let mut encoder = Encoder::new(a.clone(), b.clone(), click.clone(), SwitchType::PullUp);
+
+    encoder.update();
+    encoder.update();
+    assert_eq!(encoder.increment(), -1);
+
+    a.done();
+    b.done();
+    click.done();
+}
+
+#[test]
+fn shift_register_595_shifts_last_device_first() {
+    use State::*;
+    // Device 1 output 7, then device 0 output 0
+    let mut data_levels = vec![High];
+    data_levels.extend_from_slice(&[Low; 14]);
+    data_levels.push(High);
+    let mut data = PinMock::new(&writes(&data_levels));
+    let mut clock = PinMock::new(&writes(&[High, Low].repeat(16)));
+    let mut latch = PinMock::new(&writes(&[High, Low]));
+    let mut outputs: ShiftRegister595<_, _, _, 2> =
+        ShiftRegister595::new(data.clone(), clock.clone(), latch.clone(), 8);
+
+    outputs.set(0);
+    outputs.set(15);
+    assert!(outputs.get(0));
+    assert!(outputs.get(15));
+    assert!(!outputs.get(8));
+    outputs.flush();
+    // Nothing changed
+    outputs.flush();
+
+    data.done();
+    clock.done();
+    latch.done();
+}
+
+#[test]
+fn shift_register_595_pwm() {
+    // Output 0 at 0.75 brightness (0.5625 after the bias) is high for half of
+    // each 4 step period, output 1 stays high, only changes are shifted out
+    let latched = [0b11u8, 0b10, 0b11, 0b10];
+    let data_levels: Vec<State> = latched
+        .iter()
+        .flat_map(|byte| {
+            (0..8).rev().map(move |bit| {
+                if byte & (1 << bit) != 0 {
+                    State::High
+                } else {
+                    State::Low
+                }
+            })
+        })
+        .collect();
+    let mut data = PinMock::new(&writes(&data_levels));
+    let mut clock = PinMock::new(&writes(
+        &[State::High, State::Low].repeat(8 * latched.len()),
+    ));
+    let mut latch = PinMock::new(&writes(&[State::High, State::Low].repeat(latched.len())));
+    let mut outputs: ShiftRegister595<_, _, _, 1> =
+        ShiftRegister595::new(data.clone(), clock.clone(), latch.clone(), 4);
+
+    outputs.set(1);
+    outputs.set_brightness(0, 0.75);
+    let mut levels = Vec::new();
+    for _ in 0..8 {
+        outputs.update();
+        levels.push(outputs.get(0));
+        assert!(outputs.get(1));
+    }
+    assert_eq!(levels, [true, true, false, false].repeat(2));
+
+    data.done();
+    clock.done();
+    latch.done();
+}
+
+#[test]
+fn shift_register_4021_pins() {
+    use State::*;
+    static STATE: ShiftRegisterState<1> = ShiftRegisterState::new();
+    let mut clock = PinMock::new(&writes(&[High, Low].repeat(8)));
+    let mut load = PinMock::new(&writes(&[High, Low]));
+    // P8 first
+    let mut data = PinMock::new(&reads(&[High, Low, Low, Low, Low, Low, Low, High]));
+    let mut inputs = ShiftRegister4021::new(clock.clone(), load.clone(), data.clone(), &STATE);
+
+    inputs.update();
+    assert!(STATE.is_high(0));
+    assert!(STATE.is_high(7));
+    assert!(!STATE.is_high(3));
+
+    let mut switch = Switch::new(inputs.pin(7), SwitchType::PullDown);
+    for _ in 0..4 {
+        switch.update();
+    }
+    assert!(switch.is_rising());
+
+    clock.done();
+    load.done();
+    data.done();
+}
+
+#[test]
+fn key_matrix_events() {
+    use State::*;
+    let mut row_levels = vec![High];
+    row_levels.extend_from_slice(&[Low, High].repeat(4));
+    let mut row = PinMock::new(&writes(&row_levels));
+    let mut col0 = PinMock::new(&reads(&[Low; 4]));
+    let mut col1 = PinMock::new(&reads(&[High; 4]));
+    let mut keys = KeyMatrix::new([row.clone()], [col0.clone(), col1.clone()]);
+    assert_eq!(keys.keys(), 2);
+
+    for _ in 0..3 {
+        keys.update();
+        assert_eq!(keys.events().count(), 0);
+    }
+    keys.update();
+    assert_eq!(keys.events().collect::<Vec<_>>(), [KeyEvent::Pressed(0)]);
+    assert!(keys.is_pressed(0));
+    assert!(!keys.is_pressed(1));
+
+    row.done();
+    col0.done();
+    col1.done();
+}
+
+#[test]
+fn key_matrix_rows_press_and_release() {
+    use State::*;
+    const UPDATES: usize = 8;
+    // Key 1 is row 0 column 1, key 2 is row 1 column 0
+    let mut row_levels = vec![High];
+    row_levels.extend_from_slice(&[Low, High].repeat(UPDATES));
+    let mut row0 = PinMock::new(&writes(&row_levels));
+    let mut row1 = PinMock::new(&writes(&row_levels));
+    // Each column is read once per row on every update, key 2 is released after 4 updates
+    let mut col0_levels = [High, Low].repeat(4);
+    col0_levels.extend_from_slice(&[High, High].repeat(4));
+    let mut col0 = PinMock::new(&reads(&col0_levels));
+    let mut col1 = PinMock::new(&reads(&[Low, High].repeat(UPDATES)));
+    let mut keys = KeyMatrix::new([row0.clone(), row1.clone()], [col0.clone(), col1.clone()]);
+    assert_eq!(keys.keys(), 4);
+
+    for _ in 0..4 {
+        keys.update();
+    }
+    assert_eq!(
+        keys.events().collect::<Vec<_>>(),
+        [KeyEvent::Pressed(1), KeyEvent::Pressed(2)]
+    );
+    assert!(!keys.is_pressed(0));
+    assert!(keys.is_pressed(1));
+    assert!(keys.is_pressed(2));
+    assert!(!keys.is_pressed(3));
+
+    for _ in 0..3 {
+        keys.update();
+        assert_eq!(keys.events().count(), 0);
+    }
+    keys.update();
+    assert_eq!(keys.events().collect::<Vec<_>>(), [KeyEvent::Released(2)]);
+    assert!(keys.is_pressed(1));
+    assert!(!keys.is_pressed(2));
+
+    row0.done();
+    row1.done();
+    col0.done();
+    col1.done();
+}
+
+#[test]
+fn gate_in_edges_and_timestamps() {
+    use State::*;
+    let mut pin = PinMock::new(&reads(&[Low, High, High, Low]));
+    let mut gate = GateIn::new(pin.clone(), false);
+    assert!(!gate.is_high());
+
+    gate.update_at(100);
+    assert!(gate.is_high());
+    assert!(gate.is_rising());
+    assert_eq!(gate.timestamp(), 100);
+
+    gate.update_at(200);
+    assert!(gate.is_high());
+    assert!(!gate.is_rising());
+    assert_eq!(gate.timestamp(), 100);
+
+    gate.update_at(300);
+    assert!(!gate.is_high());
+    assert!(gate.is_falling());
+    assert_eq!(gate.timestamp(), 300);
+
+    pin.done();
+}
+
+#[test]
+fn gate_in_inverted() {
+    let mut pin = PinMock::new(&reads(&[State::High, State::Low]));
+    let mut gate = GateIn::new(pin.clone(), true);
+    gate.update_at(0);
+    assert!(gate.is_high());
+    assert!(gate.is_rising());
+    pin.done();
+}
+
+#[test]
+fn gate_in_high_at_boot_has_no_edge() {
+    let mut pin = PinMock::new(&reads(&[State::High, State::High]));
+    let mut gate = GateIn::new(pin.clone(), false);
+    assert!(gate.is_high());
+    gate.update_at(0);
+    assert!(gate.is_high());
+    assert!(!gate.is_rising());
+    pin.done();
+}
+
+#[test]
+fn gate_out_trigger() {
+    use State::*;
+    let mut pin = PinMock::new(&writes(&[Low, High, Low, High, High]));
+    // 3 ms at a 2 ms update rate rounds up to 2 updates
+    let mut gate = GateOut::new(pin.clone(), false, 2);
+    assert!(!gate.is_high());
+
+    gate.trigger(3);
+    assert!(gate.is_high());
+    gate.update();
+    assert!(gate.is_high());
+    gate.update();
+    assert!(!gate.is_high());
+    gate.update();
+
+    // Setting the gate cancels a trigger
+    gate.trigger(2);
+    gate.set(true);
+    for _ in 0..4 {
+        gate.update();
+    }
+    assert!(gate.is_high());
+
+    pin.done();
+}
+
+#[test]
+fn gate_out_inverted() {
+    use State::*;
+    let mut pin = PinMock::new(&writes(&[High, Low, High]));
+    let mut gate = GateOut::new(pin.clone(), true, 1);
+    gate.trigger(1);
+    gate.update();
+    assert!(!gate.is_high());
+    pin.done();
+}
+
+#[test]
+#[should_panic]
+fn gate_out_zero_update_rate() {
+    GateOut::new(PinMock::new(&[]), false, 0);
+}
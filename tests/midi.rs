@@ -0,0 +1,86 @@
+use libdaisy_rust::midi::*;
+
+fn parse_all(bytes: &[u8]) -> Vec<MidiMessage> {
+    let mut parser = MidiParser::new();
+    bytes
+        .iter()
+        .filter_map(|byte| parser.parse(*byte))
+        .collect()
+}
+
+#[test]
+fn channel_messages() {
+    assert_eq!(
+        parse_all(&[0x91, 60, 100, 0x81, 60, 64, 0xb2, 7, 127, 0xc3, 5, 0xe0, 0x00, 0x40]),
+        [
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 100
+            },
+            MidiMessage::NoteOff {
+                channel: 1,
+                note: 60,
+                velocity: 64
+            },
+            MidiMessage::ControlChange {
+                channel: 2,
+                control: 7,
+                value: 127
+            },
+            MidiMessage::ProgramChange {
+                channel: 3,
+                program: 5
+            },
+            MidiMessage::PitchBend {
+                channel: 0,
+                value: 8192
+            },
+        ]
+    );
+}
+
+#[test]
+fn running_status_and_zero_velocity() {
+    assert_eq!(
+        parse_all(&[0x90, 60, 100, 62, 0]),
+        [
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 60,
+                velocity: 100
+            },
+            MidiMessage::NoteOff {
+                channel: 0,
+                note: 62,
+                velocity: 0
+            },
+        ]
+    );
+}
+
+#[test]
+fn real_time_interleaved() {
+    assert_eq!(
+        parse_all(&[0x90, 0xf8, 60, 0xfa, 100]),
+        [
+            MidiMessage::Clock,
+            MidiMessage::Start,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 60,
+                velocity: 100
+            },
+        ]
+    );
+}
+
+#[test]
+fn system_messages_cancel_running_status() {
+    assert_eq!(
+        parse_all(&[0x90, 60, 100, 0xf0, 1, 2, 0xf7, 60, 0]).len(),
+        1
+    );
+    // Data without any status is ignored
+    assert!(parse_all(&[60, 100]).is_empty());
+}
@@ -0,0 +1,78 @@
+use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+
+use libdaisy_rust::pca9685::*;
+
+const ADDRESS: u8 = 0x40;
+
+/// Write of every channel of one device, `channels` gives (ON, OFF) counts
+fn pwm_write(address: u8, channels: &[(u16, u16); CHANNELS]) -> Transaction {
+    let mut bytes = vec![0x06];
+    for (on, off) in channels.iter() {
+        bytes.extend_from_slice(&on.to_le_bytes());
+        bytes.extend_from_slice(&off.to_le_bytes());
+    }
+    Transaction::write(address, bytes)
+}
+
+const OFF: (u16, u16) = (0, 0x1000);
+const ON: (u16, u16) = (0x1000, 0);
+
+#[test]
+fn init_turns_everything_off() {
+    let mut i2c = Mock::new(&[
+        Transaction::write(ADDRESS, vec![0x00, 0x20]),
+        Transaction::write(ADDRESS, vec![0x01, 0x14]),
+        pwm_write(ADDRESS, &[OFF; CHANNELS]),
+    ]);
+    let mut leds = Pca9685::new(i2c.clone(), [ADDRESS]);
+    leds.init().unwrap();
+    i2c.done();
+}
+
+#[test]
+fn flush_only_writes_changed_devices() {
+    let mut second = [OFF; CHANNELS];
+    second[0] = ON;
+    second[1] = (0, 1023);
+    let mut i2c = Mock::new(&[
+        pwm_write(ADDRESS, &[OFF; CHANNELS]),
+        pwm_write(ADDRESS + 1, &[OFF; CHANNELS]),
+        pwm_write(ADDRESS + 1, &second),
+    ]);
+    let mut leds = Pca9685::new(i2c.clone(), [ADDRESS, ADDRESS + 1]);
+    leds.flush().unwrap();
+
+    leds.set_led(16, 1.0);
+    // Brightness is squared
+    leds.set_led(17, 0.5);
+    assert_eq!(leds.get_pwm(16), 4095);
+    assert_eq!(leds.get_pwm(17), 1023);
+    leds.flush().unwrap();
+
+    // Nothing changed
+    leds.set_led(16, 1.0);
+    leds.flush().unwrap();
+
+    i2c.done();
+}
+
+#[test]
+fn set_all_clamps_brightness() {
+    let i2c = Mock::new(&[pwm_write(ADDRESS, &[ON; CHANNELS])]);
+    let mut leds = Pca9685::new(i2c, [ADDRESS]);
+    leds.set_all(2.0);
+    leds.flush().unwrap();
+
+    leds.set_all(-1.0);
+    for led in 0..CHANNELS {
+        assert_eq!(leds.get_pwm(led), 0);
+    }
+    leds.free().done();
+}
+
+#[test]
+#[should_panic]
+fn out_of_range_led_panics() {
+    let mut leds = Pca9685::new(Mock::new(&[]), [ADDRESS, ADDRESS + 1]);
+    leds.set_led(32, 1.0);
+}
@@ -0,0 +1,163 @@
+use libdaisy_rust::sample::*;
+
+#[test]
+fn s24_words_round_trip() {
+    for word in [0x00_0000, 0x00_0001, 0x7f_ffff, 0x80_0000, 0xff_ffff] {
+        assert_eq!(S24::from_word(word).to_word(), word);
+    }
+    assert_eq!(S24::from_word(0xff_ffff), S24(-1));
+    assert_eq!(S24::from_word(0x80_0000), S24(-0x80_0000));
+    // Bits above 24 are ignored
+    assert_eq!(S24::from_word(0xff00_0001), S24(1));
+}
+
+#[test]
+fn s24_raw_words_are_sign_extended() {
+    // -1 LSB as a raw 24 bit word, not sign extended into the i32
+    assert_eq!(f32::from(S24(0x00ff_ffff)), -S24::LSB);
+    assert_eq!(S24(0x0080_0000).to_f32(), -1.0);
+    assert_eq!(S24::from(0x00ff_ffff_i32), S24(-1));
+    assert_eq!(S24::from(0x00ff_ffff_u32), S24(-1));
+    assert_eq!(f32::from(S24::from(0x00ff_ffff_i32)), -S24::LSB);
+    // Already sign extended values are unchanged
+    assert_eq!(S24::from(-1), S24(-1));
+    assert_eq!(f32::from(S24(-1)), -S24::LSB);
+}
+
+#[test]
+fn integer_formats_round_trip_f32() {
+    for x in [-0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75] {
+        assert_eq!(S16::from_f32(x).to_f32(), x);
+        assert_eq!(S24::from_f32(x).to_f32(), x);
+        assert_eq!(S32::from_f32(x).to_f32(), x);
+    }
+}
+
+#[test]
+fn conversions_clip() {
+    assert_eq!(S16::from_f32(2.0), S16::from_f32(FBIPMAX));
+    assert_eq!(S16::from_f32(-2.0), S16::from_f32(FBIPMIN));
+    assert_eq!(S24::from_f32(1.0), S24::from_f32(FBIPMAX));
+    assert_eq!(S24::from_f32(-1.0), S24::from_f32(FBIPMIN));
+    assert_eq!(S32::from_f32(f32::MAX), S32::from_f32(FBIPMAX));
+    assert!(S24::from_f32(FBIPMAX).0 < 0x80_0000);
+    assert!(S32::from_f32(FBIPMAX).0 > 0);
+
+    // Float passes through unclipped
+    assert_eq!(f32::from_f32(2.0), 2.0);
+    assert_eq!(f32::from_word(2.0f32.to_word()), 2.0);
+}
+
+#[test]
+fn slice_conversions() {
+    let input = [0.5, -0.5, 0.25];
+    let mut words = [0; 3];
+    let mut output = [0.0; 3];
+
+    f32_to_s24(&input, &mut words);
+    assert_eq!(words, [0x40_0000, 0xc0_0000, 0x20_0000]);
+    s24_to_f32(&words, &mut output);
+    assert_eq!(output, input);
+
+    f32_to_s16(&input, &mut words);
+    assert_eq!(words, [0x4000, 0xc000, 0x2000]);
+    s16_to_f32(&words, &mut output);
+    assert_eq!(output, input);
+
+    f32_to_s32(&input, &mut words);
+    s32_to_f32(&words, &mut output);
+    assert_eq!(output, input);
+
+    // Stops at the shorter slice
+    let mut short = [0.0; 2];
+    s24_to_f32(&words[..1], &mut short);
+    assert_eq!(short[1], 0.0);
+}
+
+#[test]
+fn dither_none_is_plain_conversion() {
+    let mut dither = Dither::new(DitherMode::None, 1);
+    for x in [-0.3, 0.0, 0.1234] {
+        assert_eq!(dither.convert::<S16>(x), S16::from_f32(x));
+    }
+}
+
+#[test]
+fn dither_error_is_bounded_and_unbiased() {
+    let mut dither = Dither::new(DitherMode::Tpdf, 1);
+    let x = 0.3 * S16::LSB;
+    let mut sum = 0.0;
+    let n = 10_000;
+    for _ in 0..n {
+        let error = dither.convert::<S16>(x).to_f32() - x;
+        assert!(error.abs() <= 2.0 * S16::LSB);
+        sum += error;
+    }
+    // Rounding without dither would always give zero, an error of -0.3 steps
+    assert!((sum / n as f32).abs() < 0.05 * S16::LSB);
+}
+
+/// Quantisation error of a 1.5 step sine with a period of `PERIOD` samples
+fn sine_errors(mode: DitherMode) -> (Vec<f64>, Vec<f64>) {
+    const PERIOD: usize = 64;
+    let mut dither = Dither::new(mode, 1);
+    (0..PERIOD * 200)
+        .map(|n| {
+            let phase = 2.0 * core::f32::consts::PI * (n % PERIOD) as f32 / PERIOD as f32;
+            let x = 1.5 * S16::LSB * phase.sin();
+            let error = dither.convert::<S16>(x).to_f32() - x;
+            (x as f64, error as f64)
+        })
+        .unzip()
+}
+
+/// Amplitude of `harmonic` of the sine in `errors`, in steps
+fn harmonic(errors: &[f64], harmonic: usize) -> f64 {
+    let (mut re, mut im) = (0.0, 0.0);
+    for (n, e) in errors.iter().enumerate() {
+        let phase = 2.0 * core::f64::consts::PI * (harmonic * n) as f64 / 64.0;
+        re += e * phase.cos();
+        im += e * phase.sin();
+    }
+    2.0 * (re * re + im * im).sqrt() / errors.len() as f64 / S16::LSB as f64
+}
+
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+}
+
+#[test]
+fn dither_decorrelates_error_from_signal() {
+    // Plain rounding of a low level sine gives errors that follow the signal and
+    // distort it with odd harmonics
+    let (signal, errors) = sine_errors(DitherMode::None);
+    assert!(correlation(&signal, &errors).abs() > 0.1);
+    assert!(harmonic(&errors, 3) > 0.1);
+
+    for mode in [DitherMode::Tpdf, DitherMode::TpdfShaped] {
+        let (signal, errors) = sine_errors(mode);
+        assert!(correlation(&signal, &errors).abs() < 0.03);
+        for n in [1, 3, 5] {
+            assert!(harmonic(&errors, n) < 0.03);
+        }
+    }
+}
+
+#[test]
+fn dither_noise_shaping() {
+    fn lag_one_correlation(mode: DitherMode) -> f64 {
+        let (_, errors) = sine_errors(mode);
+        correlation(&errors[1..], &errors[..errors.len() - 1])
+    }
+
+    // Plain TPDF error is white, shaping makes consecutive errors anti-correlated
+    assert!(lag_one_correlation(DitherMode::Tpdf).abs() < 0.05);
+    assert!(lag_one_correlation(DitherMode::TpdfShaped) < -0.3);
+}
+
+#[test]
+fn float_is_never_dithered() {
+    let mut dither = Dither::new(DitherMode::TpdfShaped, 1);
+    assert_eq!(dither.convert::<f32>(0.123), 0.123);
+}
@@ -0,0 +1,67 @@
+use libdaisy_rust::scheduler::*;
+
+#[derive(Default)]
+struct Counts {
+    fast: u32,
+    slow: u32,
+}
+
+#[test]
+fn tasks_run_at_their_period() {
+    let mut scheduler: Scheduler<Counts, 2> = Scheduler::new(1);
+    scheduler.add("fast", 2, |c| c.fast += 1).unwrap();
+    scheduler.add("slow", 5, |c| c.slow += 1).unwrap();
+
+    let mut counts = Counts::default();
+    for _ in 0..10 {
+        scheduler.tick(&mut counts);
+    }
+    assert_eq!(counts.fast, 5);
+    assert_eq!(counts.slow, 2);
+}
+
+#[test]
+fn errors() {
+    let mut scheduler: Scheduler<Counts, 1> = Scheduler::new(2);
+    assert_eq!(scheduler.add("odd", 3, |_| {}), Err(Error::InvalidPeriod));
+    assert_eq!(scheduler.add("zero", 0, |_| {}), Err(Error::InvalidPeriod));
+    scheduler.add("task", 4, |_| {}).unwrap();
+    assert_eq!(scheduler.add("other", 4, |_| {}), Err(Error::Full));
+    assert_eq!(scheduler.remove("other"), Err(Error::NotFound));
+    assert_eq!(scheduler.set_period("task", 5), Err(Error::InvalidPeriod));
+    scheduler.remove("task").unwrap();
+    scheduler.add("other", 4, |_| {}).unwrap();
+}
+
+#[test]
+fn disable_and_change_period() {
+    let mut scheduler: Scheduler<Counts, 1> = Scheduler::new(1);
+    scheduler.add("fast", 1, |c| c.fast += 1).unwrap();
+    let mut counts = Counts::default();
+
+    scheduler.set_enabled("fast", false).unwrap();
+    scheduler.tick(&mut counts);
+    assert_eq!(counts.fast, 0);
+
+    scheduler.set_enabled("fast", true).unwrap();
+    scheduler.set_period("fast", 3).unwrap();
+    for _ in 0..6 {
+        scheduler.tick(&mut counts);
+    }
+    assert_eq!(counts.fast, 2);
+}
+
+#[test]
+fn periods_are_counted_in_ticks() {
+    // A 2 ms tick runs a 6 ms task every third tick
+    let mut scheduler: Scheduler<Counts, 2> = Scheduler::new(2);
+    scheduler.add("fast", 2, |c| c.fast += 1).unwrap();
+    scheduler.add("slow", 6, |c| c.slow += 1).unwrap();
+
+    let mut counts = Counts::default();
+    for tick in 1..=6 {
+        scheduler.tick(&mut counts);
+        assert_eq!(counts.fast, tick);
+        assert_eq!(counts.slow, tick / 3);
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "sim")]
+
+use libdaisy_rust::audio::Frame;
+use libdaisy_rust::sim::*;
+
+#[test]
+fn wav_round_trip() {
+    let wav = Wav {
+        sample_rate: 48_000,
+        channels: 2,
+        samples: vec![0.5, -0.5, 0.25, -0.25, 0.0, 0.125],
+    };
+    let bytes = wav.to_bytes();
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(bytes.len(), 44 + 6 * 3);
+    assert_eq!(Wav::parse(&bytes).unwrap(), wav);
+}
+
+#[test]
+fn wav_rejects_other_files() {
+    assert!(matches!(
+        Wav::parse(b"not a wav file"),
+        Err(Error::Format(_))
+    ));
+}
+
+#[test]
+fn process_pads_the_last_block() {
+    let simulator = Simulator::new(48_000, 4);
+    let mut blocks = 0;
+    let mut callback = |input: &[Frame<2>], output: &mut [Frame<2>]| {
+        assert_eq!(input.len(), 4);
+        blocks += 1;
+        for (out, frame) in output.iter_mut().zip(input.iter()) {
+            *out = [frame[1], frame[0]];
+        }
+    };
+    let input: Vec<Frame<2>> = (0..6).map(|i| [i as f32 * 0.125, 0.0]).collect();
+    let output = simulator.process(&mut callback, &input);
+
+    assert_eq!(blocks, 2);
+    assert_eq!(output.len(), 6);
+    assert_eq!(output[5], [0.0, 0.625]);
+}
+
+#[test]
+fn default_matches_the_hardware_callback() {
+    let simulator = Simulator::default();
+    assert_eq!(
+        simulator.block_size(),
+        libdaisy_rust::audio::FRAMES_PER_CALLBACK
+    );
+    let mut calls = 0;
+    let mut callback = |input: &[Frame<2>], _: &mut [Frame<2>]| {
+        assert_eq!(input.len(), 1);
+        calls += 1;
+    };
+    simulator.process(&mut callback, &[[0.0; 2]; 3]);
+    assert_eq!(calls, 3);
+}
+
+#[test]
+fn wav_size_is_not_trusted() {
+    // The data size isn't used to allocate, so a size past the end of the file is a clean error
+    let mut bytes = Wav {
+        sample_rate: 48_000,
+        channels: 1,
+        samples: vec![0.5],
+    }
+    .to_bytes();
+    bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(Wav::parse(&bytes), Err(Error::Format(_))));
+}
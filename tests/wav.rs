@@ -0,0 +1,314 @@
+use libdaisy_rust::audio::Frame;
+use libdaisy_rust::sample::{S16, S24};
+use libdaisy_rust::wav::*;
+
+const ENCODINGS: [Encoding; 4] = [
+    Encoding::Pcm16,
+    Encoding::Pcm24,
+    Encoding::Pcm32,
+    Encoding::Float32,
+];
+
+const FRAMES: [Frame<2>; 3] = [[0.5, -0.5], [0.25, -0.25], [0.0, 0.125]];
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+fn write(format: Format, frames: &[Frame<2>], buffer: &mut [u8]) -> usize {
+    let mut writer = Writer::new(SliceWriter::new(buffer), format).unwrap();
+    writer.write_frames(frames).unwrap();
+    assert_eq!(writer.frames_written(), frames.len());
+    writer.finalize().unwrap().len()
+}
+
+/// Header with the given chunks after fmt, then a data chunk
+fn file(format: &[u8], chunks: &[(&[u8; 4], &[u8])], data: &[u8]) -> Vec<u8> {
+    let mut bytes = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+    bytes.extend_from_slice(&(format.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(format);
+    for (id, body) in chunks {
+        bytes.extend_from_slice(*id);
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+    }
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// fmt chunk body for 16 bit PCM
+fn pcm16_format(channels: u16) -> Vec<u8> {
+    let mut format = vec![0x01, 0x00];
+    format.extend_from_slice(&channels.to_le_bytes());
+    format.extend_from_slice(&44_100u32.to_le_bytes());
+    format.extend_from_slice(&(44_100 * 2 * channels as u32).to_le_bytes());
+    format.extend_from_slice(&(2 * channels).to_le_bytes());
+    format.extend_from_slice(&16u16.to_le_bytes());
+    format
+}
+
+#[test]
+fn round_trip() {
+    for encoding in ENCODINGS.iter() {
+        let format = Format {
+            encoding: *encoding,
+            channels: 2,
+            sample_rate: 96_000,
+        };
+        let mut buffer = [0; 128];
+        let len = write(format, &FRAMES, &mut buffer);
+        let data_size = FRAMES.len() * format.block_align();
+        assert_eq!(len, HEADER_SIZE + data_size);
+        assert_eq!(u32_at(&buffer, 4) as usize, len - 8);
+        assert_eq!(u32_at(&buffer, 40) as usize, data_size);
+
+        let mut reader = Reader::new(&buffer[..len]).unwrap();
+        assert_eq!(reader.format(), format);
+        assert_eq!(reader.frames_remaining(), 3);
+        let mut frames = [[1.0; 2]; 4];
+        assert_eq!(reader.read_frames(&mut frames).unwrap(), 3);
+        assert_eq!(frames[..3], FRAMES);
+        assert_eq!(reader.read_frames(&mut frames).unwrap(), 0);
+    }
+}
+
+#[test]
+fn odd_data_is_padded() {
+    let format = Format {
+        encoding: Encoding::Pcm24,
+        channels: 1,
+        sample_rate: 48_000,
+    };
+    let mut buffer = [0xff; 64];
+    let mut writer = Writer::new(SliceWriter::new(&mut buffer), format).unwrap();
+    writer.write_samples(&[S24(1)]).unwrap();
+    let len = writer.finalize().unwrap().len();
+    assert_eq!(len, HEADER_SIZE + 4);
+    assert_eq!(u32_at(&buffer, 4) as usize, len - 8);
+    assert_eq!(u32_at(&buffer, 40), 3);
+    assert_eq!(buffer[HEADER_SIZE..len], [1, 0, 0, 0]);
+}
+
+#[test]
+fn samples_convert_between_formats() {
+    let format = Format {
+        encoding: Encoding::Pcm16,
+        channels: 1,
+        sample_rate: 48_000,
+    };
+    let mut buffer = [0; 64];
+    let mut writer = Writer::new(SliceWriter::new(&mut buffer), format).unwrap();
+    writer
+        .write_samples(&[S24(0x10_0000), S24(-0x40_0000)])
+        .unwrap();
+    let len = writer.finalize().unwrap().len();
+    assert_eq!(buffer[HEADER_SIZE..len], [0x00, 0x10, 0x00, 0xc0]);
+
+    let mut reader = Reader::new(&buffer[..len]).unwrap();
+    let mut samples = [S16(0); 2];
+    assert_eq!(reader.read_samples(&mut samples).unwrap(), 2);
+    assert_eq!(samples, [S16(0x1000), S16(-0x4000)]);
+}
+
+#[test]
+fn mono_is_copied_to_every_channel() {
+    let data = [0x00, 0x40, 0x00, 0xc0];
+    let bytes = file(&pcm16_format(1), &[], &data);
+    let mut reader = Reader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.format().sample_rate, 44_100);
+    let mut frames = [[0.0; 2]; 2];
+    assert_eq!(reader.read_frames(&mut frames).unwrap(), 2);
+    assert_eq!(frames, [[0.5, 0.5], [-0.5, -0.5]]);
+}
+
+#[test]
+fn channel_mismatch() {
+    let bytes = file(&pcm16_format(2), &[], &[0; 4]);
+    let mut reader = Reader::new(&bytes[..]).unwrap();
+    let mut frames = [[0.0; 4]; 1];
+    assert_eq!(
+        reader.read_frames(&mut frames),
+        Err(Error::Channels {
+            expected: 4,
+            found: 2
+        })
+    );
+
+    let format = Format {
+        encoding: Encoding::Pcm16,
+        channels: 1,
+        sample_rate: 48_000,
+    };
+    let mut buffer = [0; 64];
+    let mut writer = Writer::new(SliceWriter::new(&mut buffer), format).unwrap();
+    assert_eq!(
+        writer.write_frames(&FRAMES),
+        Err(Error::Channels {
+            expected: 2,
+            found: 1
+        })
+    );
+}
+
+#[test]
+fn other_chunks_are_skipped() {
+    let bytes = file(
+        &pcm16_format(1),
+        &[(b"LIST", b"odd"), (b"junk", &[0; 200])],
+        &[0x00, 0x40],
+    );
+    let mut reader = Reader::new(&bytes[..]).unwrap();
+    let mut samples = [0.0; 2];
+    assert_eq!(reader.read_samples(&mut samples).unwrap(), 1);
+    assert_eq!(samples[0], 0.5);
+}
+
+#[test]
+fn extensible_format() {
+    let mut format = pcm16_format(1);
+    format[0..2].copy_from_slice(&0xfffeu16.to_le_bytes());
+    format[14] = 32;
+    // cbSize, valid bits, channel mask, then the sub format GUID
+    format.extend_from_slice(&[22, 0, 32, 0, 4, 0, 0, 0]);
+    format.extend_from_slice(&[0x03, 0x00]);
+    format.extend_from_slice(&[0; 14]);
+    let bytes = file(&format, &[], &[]);
+    assert_eq!(
+        Reader::new(&bytes[..]).unwrap().format().encoding,
+        Encoding::Float32
+    );
+
+    // 8 bit isn't supported
+    let mut format = pcm16_format(1);
+    format[14] = 8;
+    assert!(matches!(
+        Reader::new(&file(&format, &[], &[])[..]),
+        Err(Error::Format(_))
+    ));
+}
+
+#[test]
+fn malformed_files() {
+    assert!(matches!(
+        Reader::new(&b"RIFF\0\0\0\0AVI LIST"[..]),
+        Err(Error::Format(_))
+    ));
+    assert!(matches!(
+        Reader::new(&b"RIFF"[..]),
+        Err(Error::UnexpectedEnd)
+    ));
+
+    let bytes = file(&pcm16_format(1), &[], &[]);
+    // Data chunk without fmt chunk
+    assert!(matches!(
+        Reader::new(
+            &bytes[..12]
+                .iter()
+                .chain(&bytes[36..])
+                .copied()
+                .collect::<Vec<_>>()[..]
+        ),
+        Err(Error::Format(_))
+    ));
+
+    // Chunk sizes that overflow with their pad byte
+    for id in [b"LIST", b"fmt "] {
+        let mut bytes = file(&pcm16_format(1), &[(id, &[])], &[]);
+        bytes[36 + 4..36 + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Reader::new(&bytes[..]), Err(Error::Format(_))));
+    }
+
+    // Data chunk shorter than its size
+    let mut bytes = file(&pcm16_format(1), &[], &[0; 4]);
+    bytes.truncate(bytes.len() - 1);
+    let mut reader = Reader::new(&bytes[..]).unwrap();
+    let mut samples = [0.0; 2];
+    assert_eq!(reader.read_samples(&mut samples), Err(Error::UnexpectedEnd));
+}
+
+/// Hands out one byte per read
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    type Error = ();
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = buffer.len().min(1);
+        Read::read(&mut self.0, &mut buffer[..n]).map_err(|_| ())
+    }
+}
+
+#[test]
+fn short_reads() {
+    let format = Format {
+        encoding: Encoding::Float32,
+        channels: 2,
+        sample_rate: 48_000,
+    };
+    let mut buffer = [0; 128];
+    let len = write(format, &FRAMES, &mut buffer);
+    let mut reader = Reader::new(Trickle(&buffer[..len])).unwrap();
+    let mut frames = [[0.0; 2]; 3];
+    assert_eq!(reader.read_frames(&mut frames).unwrap(), 3);
+    assert_eq!(frames, FRAMES);
+}
+
+#[test]
+fn buffer_full() {
+    let format = Format {
+        encoding: Encoding::Pcm16,
+        channels: 2,
+        sample_rate: 48_000,
+    };
+    let mut buffer = [0; HEADER_SIZE + 4];
+    let mut writer = Writer::new(SliceWriter::new(&mut buffer), format).unwrap();
+    writer.write_frames(&FRAMES[..1]).unwrap();
+    assert_eq!(
+        writer.write_frames(&FRAMES[..1]),
+        Err(Error::Io(BufferFull))
+    );
+
+    let mut buffer = [0; 8];
+    assert!(matches!(
+        Writer::new(SliceWriter::new(&mut buffer), format),
+        Err(Error::Io(BufferFull))
+    ));
+}
+
+#[test]
+fn byte_rate_overflow() {
+    let format = Format {
+        encoding: Encoding::Float32,
+        channels: 2,
+        sample_rate: u32::MAX / 4,
+    };
+    let mut buffer = [0; 64];
+    assert!(matches!(
+        Writer::new(SliceWriter::new(&mut buffer), format),
+        Err(Error::Format(_))
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn std_io() {
+    let format = Format {
+        encoding: Encoding::Pcm24,
+        channels: 2,
+        sample_rate: 48_000,
+    };
+    let mut writer = Writer::new(Io(std::io::Cursor::new(Vec::new())), format).unwrap();
+    writer.write_frames(&FRAMES).unwrap();
+    let Io(cursor) = writer.finalize().unwrap();
+    assert_eq!(cursor.position() as usize, cursor.get_ref().len());
+
+    let mut reader = Reader::new(Io(&cursor.get_ref()[..])).unwrap();
+    let mut frames = [[0.0; 2]; 3];
+    assert_eq!(reader.read_frames(&mut frames).unwrap(), 3);
+    assert_eq!(frames, FRAMES);
+}